@@ -0,0 +1,111 @@
+//! Serializes diagnostics as JSON, for tools like editors that want to read
+//! them without parsing the rendered text.
+
+use super::{Diagnostic, DiagnosticPart};
+use crate::{source_map::SourceMap, utils::json::write_string};
+use std::fmt::Write;
+
+/// Write `diag` as a JSON object of the form:
+///
+/// ```json
+/// {
+///   "message": "...",
+///   "severity": "error" | "warning" | "note",
+///   "code": "E0001" | null,
+///   "parts": [{
+///     "file": "...",
+///     "line": 1, "col": 1, "end_line": 1, "end_col": 2,
+///     "byte_start": 0, "byte_end": 1,
+///     "help": "..."
+///   }],
+///   "notes": ["..."]
+/// }
+/// ```
+///
+/// Lines and columns start at 1 and the end of a part is exclusive, like its
+/// byte range.
+pub fn write_diagnostic(out: &mut String, diag: &Diagnostic, sm: &SourceMap) {
+    out.push_str("{\"message\":");
+    write_string(out, &diag.msg);
+
+    out.push_str(",\"severity\":");
+    write_string(out, diag.severity.label());
+
+    out.push_str(",\"code\":");
+    match &diag.code {
+        Some(code) => write_string(out, code),
+        None => out.push_str("null"),
+    }
+
+    out.push_str(",\"parts\":[");
+    for (i, part) in diag.parts.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_part(out, part, sm);
+    }
+
+    out.push_str("],\"notes\":[");
+    for (i, note) in diag.notes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, note);
+    }
+    out.push_str("]}");
+}
+
+/// Append the JSON object for `part`, with the file and line and column range
+/// it points at.
+fn write_part(out: &mut String, part: &DiagnosticPart, sm: &SourceMap) {
+    let source = sm.get_source(part.span.src_id());
+    let (start, end) = (part.span.start(), part.span.end());
+
+    out.push_str("{\"file\":");
+    write_string(out, source.filename());
+    write!(
+        out,
+        ",\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{},\"byte_start\":{},\"byte_end\":{}",
+        source.line_of(start),
+        source.col_of(start),
+        source.line_of(end),
+        source.col_of(end),
+        start.byte(),
+        end.byte(),
+    )
+    .unwrap();
+
+    out.push_str(",\"help\":");
+    write_string(out, &part.help);
+    out.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostics::{render_all_json, Diagnostic},
+        source_map::SourceMap,
+    };
+
+    #[test]
+    fn parts_have_lines_and_columns() {
+        let mut sm = SourceMap::new();
+        let text = "fn f() {\n    \"é\" + y\n}\n";
+        let id = sm.add_source("test.fern".to_owned(), text.to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `y`".to_owned())
+            .with_code("E0100".to_owned())
+            .add_part(source.span(20, 21), "not found".to_owned())
+            .add_note("declare it first".to_owned());
+
+        // Columns count characters, so the `é` only takes up one.
+        let expected = concat!(
+            r#"{"message":"unknown variable `y`","severity":"error","code":"E0100","parts":["#,
+            r#"{"file":"test.fern","line":2,"col":11,"end_line":2,"end_col":12,"#,
+            r#""byte_start":20,"byte_end":21,"help":"not found"}],"notes":["declare it first"]}"#,
+        );
+        assert_eq!(diag.render_json(&sm), expected);
+        let all = render_all_json([&diag, &diag], &sm);
+        assert_eq!(all, format!("[{expected},{expected}]"));
+    }
+}
@@ -1,39 +1,478 @@
-use crate::source_map::{Source, SourceMap, SourcePos, Span};
+use crate::source_map::{Source, SourceId, SourceMap, SourcePos, Span};
 use render::DiagWriter;
+pub use render::{ColorChoice, Theme};
 use std::{
     fmt::{self, Write},
-    io::repeat,
-    usize,
+    io,
 };
 
+mod json;
 mod render;
 pub mod specifics;
 
+/// How serious a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something is wrong with the code, so it can't be compiled.
+    Error,
+
+    /// The code compiles, but probably doesn't do what was meant.
+    Warning,
+
+    /// Extra information that isn't a problem in itself.
+    Note,
+}
+
+impl Severity {
+    /// The name diagnostics of this severity are labeled with.
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 struct DiagnosticPart {
     span: Span,
     help: String,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub struct Diagnostic {
     msg: String,
+
+    /// How serious the problem is. This is `Severity::Error` unless set
+    /// otherwise.
+    severity: Severity,
+
+    /// A stable identifier for the kind of problem, like `E0001`.
+    code: Option<String>,
     parts: Vec<DiagnosticPart>,
+
+    /// Extra information shown below the code, which isn't about any span in
+    /// particular.
+    notes: Vec<String>,
 }
 
 impl Diagnostic {
+    /// An error with the message `msg` and no parts or notes yet.
     pub fn new(msg: String) -> Self {
         Self {
             msg,
+            severity: Severity::Error,
+            code: None,
             parts: Vec::new(),
+            notes: Vec::new(),
         }
     }
 
+    /// Set how serious the problem is.
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Set the stable identifier of the kind of problem, like `E0001`.
+    pub fn with_code(mut self, code: String) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Point at `span` with the message `help`, which may be empty.
     pub fn add_part(mut self, span: Span, help: String) -> Self {
         self.parts.push(DiagnosticPart { span, help });
         self
     }
 
+    /// Add a note shown below the code.
+    pub fn add_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Combine two diagnostics about the same problem. The message and
+    /// severity and code of `self` are kept and the parts and notes of `other` are
+    /// added after those of `self`, skipping any that are identical.
+    pub fn merge(mut self, other: Diagnostic) -> Diagnostic {
+        for part in other.parts {
+            if !self.parts.contains(&part) {
+                self.parts.push(part);
+            }
+        }
+
+        for note in other.notes {
+            if !self.notes.contains(&note) {
+                self.notes.push(note);
+            }
+        }
+
+        self
+    }
+
+    /// How serious the problem is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The position this diagnostic is primarily about, used to order
+    /// diagnostics. This is the start of the first part, if there is one.
+    fn primary_pos(&self) -> Option<(SourceId, usize)> {
+        let span = self.parts.first()?.span;
+        Some((span.src_id(), span.start().byte()))
+    }
+
+    /// Render this diagnostic, with colors if stdout is a terminal. See
+    /// `ColorChoice::Auto`.
     pub fn render(&self, wr: &mut impl Write, sm: &SourceMap) -> Result<(), fmt::Error> {
-        let mut writer = DiagWriter::new_ansi(wr);
+        let mut writer = DiagWriter::with_theme(wr, Theme::default());
+        render::render(&mut writer, self, sm)
+    }
+
+    /// Render this diagnostic without any colors or other escape sequences,
+    /// for output that isn't going to a terminal.
+    pub fn render_plain(&self, wr: &mut impl Write, sm: &SourceMap) -> Result<(), fmt::Error> {
+        let mut writer = DiagWriter::new_plain(wr);
         render::render(&mut writer, self, sm)
     }
+
+    /// Render this diagnostic with the colors, hyperlinks and layout of
+    /// `theme`.
+    pub fn render_with_theme(
+        &self,
+        wr: &mut impl Write,
+        sm: &SourceMap,
+        theme: Theme,
+    ) -> Result<(), fmt::Error> {
+        let mut writer = DiagWriter::with_theme(wr, theme);
+        render::render(&mut writer, self, sm)
+    }
+
+    /// Serialize this diagnostic as a JSON object. See
+    /// `json::write_diagnostic` for its layout.
+    pub fn render_json(&self, sm: &SourceMap) -> String {
+        let mut out = String::new();
+        json::write_diagnostic(&mut out, self, sm);
+        out
+    }
+
+    /// Render this diagnostic without any colors, for comparing against the
+    /// expected layout in tests.
+    #[cfg(test)]
+    pub fn render_plain_string(&self, sm: &SourceMap) -> String {
+        let mut out = String::new();
+        self.render(&mut out, sm).unwrap();
+        crate::testing::strip_ansi(&out)
+    }
+}
+
+/// Render each diagnostic into `wr`, flushing after every one so they show up
+/// as soon as they are rendered.
+pub fn render_all<'a>(
+    diags: impl IntoIterator<Item = &'a Diagnostic>,
+    wr: &mut impl io::Write,
+    sm: &SourceMap,
+) -> io::Result<()> {
+    for diag in diags {
+        let mut out = String::new();
+        diag.render(&mut out, sm).map_err(io::Error::other)?;
+        out.push('\n');
+
+        wr.write_all(out.as_bytes())?;
+        wr.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Serialize the diagnostics as a JSON array of the objects produced by
+/// `Diagnostic::render_json`.
+pub fn render_all_json<'a>(
+    diags: impl IntoIterator<Item = &'a Diagnostic>,
+    sm: &SourceMap,
+) -> String {
+    let mut out = String::from("[");
+
+    for (i, diag) in diags.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json::write_diagnostic(&mut out, diag, sm);
+    }
+
+    out.push(']');
+    out
+}
+
+/// A collection of `Diagnostic`s produced by one or more compiler stages.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// The diagnostics in the order they were reported.
+    diags: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// An empty collection.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a diagnostic after all the others.
+    pub fn push(&mut self, diag: Diagnostic) {
+        self.diags.push(diag);
+    }
+
+    /// Whether nothing was reported.
+    pub fn is_empty(&self) -> bool {
+        self.diags.is_empty()
+    }
+
+    /// The number of diagnostics, of any severity.
+    pub fn len(&self) -> usize {
+        self.diags.len()
+    }
+
+    /// An iterator over the diagnostics in the order they were reported.
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diags.iter()
+    }
+
+    /// The number of diagnostics with the given severity.
+    pub fn count_of(&self, severity: Severity) -> usize {
+        self.iter().filter(|d| d.severity() == severity).count()
+    }
+
+    /// The number of errors.
+    pub fn error_count(&self) -> usize {
+        self.count_of(Severity::Error)
+    }
+
+    /// The number of warnings.
+    pub fn warning_count(&self) -> usize {
+        self.count_of(Severity::Warning)
+    }
+
+    /// Order the diagnostics by the source position they point at.
+    /// Diagnostics without a position come first, and the sort is stable so
+    /// ties keep the order they were reported in.
+    pub fn sorted(mut self) -> Self {
+        self.diags.sort_by_key(Diagnostic::primary_pos);
+        self
+    }
+
+    /// Remove any diagnostic identical to one reported before it.
+    pub fn deduped(self) -> Self {
+        let mut diags: Vec<Diagnostic> = Vec::with_capacity(self.len());
+
+        for diag in self.diags {
+            if !diags.contains(&diag) {
+                diags.push(diag);
+            }
+        }
+
+        Self { diags }
+    }
+
+    /// Keep at most the first `max` diagnostics.
+    pub fn capped(mut self, max: usize) -> Self {
+        self.diags.truncate(max);
+        self
+    }
+}
+
+impl From<Vec<Diagnostic>> for Diagnostics {
+    fn from(diags: Vec<Diagnostic>) -> Self {
+        Self { diags }
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diag: Diagnostic) -> Self {
+        Self { diags: vec![diag] }
+    }
+}
+
+impl FromIterator<Diagnostic> for Diagnostics {
+    fn from_iter<I: IntoIterator<Item = Diagnostic>>(iter: I) -> Self {
+        Self {
+            diags: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.diags.extend(iter);
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diags.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diags.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sink that records how many bytes were written to it by the time of
+    /// each flush.
+    #[derive(Default)]
+    struct FlushRecorder {
+        written: usize,
+        flushes: Vec<usize>,
+    }
+
+    impl io::Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.written);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn counts_by_severity() {
+        let diags: Diagnostics = [
+            Severity::Error,
+            Severity::Warning,
+            Severity::Error,
+            Severity::Note,
+        ]
+        .into_iter()
+        .map(|severity| Diagnostic::new(String::new()).with_severity(severity))
+        .collect();
+
+        assert_eq!(diags.len(), 4);
+        assert_eq!(diags.error_count(), 2);
+        assert_eq!(diags.warning_count(), 1);
+        assert_eq!(diags.count_of(Severity::Note), 1);
+    }
+
+    #[test]
+    fn sorts_by_position_and_dedups() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = y;\n".to_owned());
+        let source = sm.get_source(id);
+        let at = |msg: &str, start: usize| {
+            Diagnostic::new(msg.to_owned()).add_part(source.span(start, start + 1), String::new())
+        };
+
+        let diags: Diagnostics = vec![
+            at("y", 8),
+            at("x", 4),
+            Diagnostic::new("nowhere".to_owned()),
+            at("also x", 4),
+            at("y", 8),
+        ]
+        .into();
+        let messages: Vec<String> = diags
+            .sorted()
+            .deduped()
+            .iter()
+            .map(|diag| diag.msg.clone())
+            .collect();
+
+        assert_eq!(messages, ["nowhere", "x", "also x", "y"]);
+    }
+
+    #[test]
+    fn renders_parts_and_notes() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = y;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `y`".to_owned())
+            .with_code("E0100".to_owned())
+            .add_part(source.span(8, 9), "not found in this scope".to_owned())
+            .add_note("variables must be declared before they are used".to_owned());
+
+        let expected = "\
+error[E0100]: unknown variable `y`
+ --> test.fern:1:9
+  |
+1 | let x = y;
+  |         ^ not found in this scope
+  |
+  = note: variables must be declared before they are used
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+
+    #[test]
+    fn render_all_flushes_each_diagnostic() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = y;\n".to_owned());
+        let source = sm.get_source(id);
+        let diags = [
+            Diagnostic::new("first".to_owned()).add_part(source.span(4, 5), String::new()),
+            Diagnostic::new("second".to_owned()).add_part(source.span(8, 9), String::new()),
+            Diagnostic::new("third".to_owned()),
+        ];
+
+        let mut sink = FlushRecorder::default();
+        render_all(&diags, &mut sink, &sm).unwrap();
+
+        assert_eq!(sink.flushes.len(), diags.len());
+        assert!(sink.flushes.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sink.flushes.last(), Some(&sink.written));
+    }
+
+    #[test]
+    fn merge_combines_parts_and_notes() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "fn f() {}\nfn f(x) {}\n".to_owned());
+        let source = sm.get_source(id);
+        let redefined = Diagnostic::new("function `f` is defined twice".to_owned())
+            .add_part(source.span(13, 14), "redefined here".to_owned())
+            .add_part(source.span(3, 4), "first defined here".to_owned());
+        let signature = Diagnostic::new("signature of `f` differs".to_owned())
+            .add_part(source.span(13, 14), "redefined here".to_owned())
+            .add_part(source.span(14, 17), "this takes one parameter".to_owned())
+            .add_note("functions can't be overloaded".to_owned());
+
+        let expected = Diagnostic::new("function `f` is defined twice".to_owned())
+            .add_part(source.span(13, 14), "redefined here".to_owned())
+            .add_part(source.span(3, 4), "first defined here".to_owned())
+            .add_part(source.span(14, 17), "this takes one parameter".to_owned())
+            .add_note("functions can't be overloaded".to_owned());
+        assert_eq!(redefined.merge(signature), expected);
+    }
+
+    #[test]
+    fn renders_span_past_the_end() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = y;\n".to_owned());
+        let source = sm.get_source(id);
+
+        // Tooling could hand us a span for a newer version of the text.
+        let span = Span::new(SourcePos::new(id, 8), SourcePos::new(id, 40));
+        assert_eq!(source.text_of_span(span), "");
+        let diag = Diagnostic::new("stale span".to_owned()).add_part(span, "here".to_owned());
+
+        // The span is cut off at the end of the text instead of panicking.
+        let rendered = diag.render_plain_string(&sm);
+        assert!(
+            rendered.starts_with("error: stale span\n --> test.fern:1:9\n"),
+            "{rendered}"
+        );
+    }
 }
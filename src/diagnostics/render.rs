@@ -1,9 +1,16 @@
-use super::Diagnostic;
+use super::{Diagnostic, Severity};
 use crate::{
     diagnostics::DiagnosticPart,
     source_map::{Source, SourceMap, SourcePos, Span},
 };
-use std::fmt::{self, Write};
+use std::{
+    env,
+    ffi::OsStr,
+    fmt::{self, Write},
+    io::{self, IsTerminal},
+    path::Path,
+};
+use unicode_width::UnicodeWidthStr;
 
 pub fn render<W: Write>(
     wr: &mut DiagWriter<W>,
@@ -12,36 +19,58 @@ pub fn render<W: Write>(
 ) -> Result<(), fmt::Error> {
     use DiagnosticRenderLine as DRL;
 
-    let mut lines = Vec::new();
-
-    // Arrange the parts by their start position. TODO: handle overlapping
-    // parts.
-    let mut parts: Vec<&DiagnosticPart> = diag.parts.iter().collect();
-    parts.sort_by_key(|p| p.span.start().byte());
+    // First we find what to underline on each source line. Each line a span
+    // touches is underlined separately, and the help message goes after the
+    // underline on the last one.
+    let mut highlights: Vec<(&Source, usize, Span, &str)> = Vec::new();
 
-    // First we assemble all the lines to be rendered.
-    for part in parts {
+    for part in &diag.parts {
         let source = sm.get_source(part.span.src_id());
 
         let start_line = source.line_of(part.span.start());
-        let end_line = source.line_of(part.span.end());
-        assert!(start_line == end_line, "TODO: handle multi line messages");
-
-        lines.push(DRL::SourcePos(part.span.start()));
-        lines.push(DRL::Padding);
+        let end_line = source.end_line_of(part.span);
 
         for line in start_line..=end_line {
-            lines.push(DRL::CodeLine { source, line });
+            let line_span = source.span_of_line(line);
+            let start = part.span.start().byte().max(line_span.start().byte());
+            let end = part.span.end().byte().min(line_span.end().byte());
+            let message = if line == end_line { &part.help[..] } else { "" };
+
+            highlights.push((source, line, source.span(start, end.max(start)), message));
+        }
+    }
 
-            // If this is the line the highlight is on then we add it here.
-            if line == start_line {
-                lines.push(DRL::Highlight {
-                    span: part.span,
-                    message: &part.help,
-                });
+    // Arrange the highlights by their position so all of those on one line
+    // end up together. The sort is stable, so highlights at the same
+    // position keep the order of their parts.
+    highlights.sort_by_key(|&(source, line, span, _)| (source.id(), line, span.start().byte()));
+
+    // Then we assemble all the lines to be rendered. Consecutive source lines
+    // are shown in one section under a single location, and a line with
+    // several highlights is shown once with each highlight below it.
+    let mut lines = Vec::new();
+    let mut prev: Option<(&Source, usize)> = None;
+
+    for (source, line, span, message) in highlights {
+        let same_line = prev.is_some_and(|(s, l)| s.id() == source.id() && l == line);
+        let next_line = prev.is_some_and(|(s, l)| s.id() == source.id() && l + 1 == line);
+
+        if !same_line && !next_line {
+            if prev.is_some() {
+                lines.push(DRL::Padding);
             }
+            lines.push(DRL::SourcePos(span.start()));
+            lines.push(DRL::Padding);
         }
+        if !same_line {
+            lines.push(DRL::CodeLine { source, line });
+        }
+        lines.push(DRL::Highlight { span, message });
+
+        prev = Some((source, line));
+    }
 
+    if prev.is_some() {
         lines.push(DRL::Padding);
     }
 
@@ -57,8 +86,14 @@ pub fn render<W: Write>(
         lines.push(all_lines[i]);
     }
 
+    // The notes go at the bottom, separated from any code above them.
+    if !diag.notes.is_empty() && !lines.is_empty() {
+        lines.push(DRL::Padding);
+    }
+    lines.extend(diag.notes.iter().map(|note| DRL::Note(note)));
+
     // Now we can perform the actual rendering.
-    wr.write_error(&diag.msg)?;
+    wr.write_header(diag.severity, diag.code.as_deref(), &diag.msg)?;
 
     let gutter_width = lines.iter().map(DRL::gutter_width).max().unwrap_or(0);
 
@@ -72,25 +107,115 @@ pub fn render<W: Write>(
             DRL::CodeLine { source, line } => wr.write_code(source, line, gutter_width)?,
             DRL::Highlight { span, message } => {
                 let source = sm.get_source(span.src_id());
-                wr.write_highlight(source, span, gutter_width, &message)?;
+                wr.write_highlight(diag.severity, source, span, gutter_width, message)?;
             }
+            DRL::Note(note) => wr.write_note(gutter_width, note)?,
         }
     }
 
     Ok(())
 }
 
+/// Makes the following text bold.
 const BOLD: &str = "\x1b[1m";
+/// Makes the following text bright red.
 const RED_FG: &str = "\x1b[91m";
+/// Makes the following text bright yellow.
+const YELLOW_FG: &str = "\x1b[93m";
+/// Makes the following text bright blue.
 const BLUE_FG: &str = "\x1b[94m";
+/// Removes all styling from the following text.
 const RESET: &str = "\x1b[0m";
 
+/// The escape sequences a `DiagWriter` styles its output with. These are all
+/// empty when rendering plain text.
+#[derive(Debug, Clone, Copy)]
+struct Palette {
+    /// Used for the header and the gutter.
+    bold: &'static str,
+
+    /// The color of errors.
+    red: &'static str,
+
+    /// The color of warnings.
+    yellow: &'static str,
+
+    /// The color of the gutter and the `-->` location.
+    blue: &'static str,
+
+    /// Ends any of the other styles.
+    reset: &'static str,
+}
+
+/// The palette for terminals that understand ANSI escape sequences.
+const ANSI_PALETTE: Palette = Palette {
+    bold: BOLD,
+    red: RED_FG,
+    yellow: YELLOW_FG,
+    blue: BLUE_FG,
+    reset: RESET,
+};
+
+/// The palette for plain text, which doesn't style anything.
+const PLAIN_PALETTE: Palette = Palette {
+    bold: "",
+    red: "",
+    yellow: "",
+    blue: "",
+    reset: "",
+};
+
+/// Starts an OSC 8 hyperlink. It is followed by the url and `ST`, then the
+/// link text, then `OSC8` and `ST` again to end the link.
+const OSC8: &str = "\x1b]8;;";
+/// The string terminator for OSC escape sequences.
+const ST: &str = "\x1b\\";
+
+/// The `file://` url of the absolute `path`. Every byte other than `/` and
+/// the unreserved characters of RFC 3986 is percent-encoded, so spaces and
+/// escape sequences in the path can't break the hyperlink.
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for &b in path.to_string_lossy().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            url.push(b as char);
+        } else {
+            // Writing to a `String` can't fail.
+            let _ = write!(url, "%{b:02X}");
+        }
+    }
+    url
+}
+
+/// A line of a rendered diagnostic below its header.
 #[derive(Debug, Clone, Copy)]
 enum DiagnosticRenderLine<'a> {
+    /// The `-->` location of the code below it.
     SourcePos(SourcePos),
+
+    /// A line with nothing but the gutter, separating sections.
     Padding,
-    CodeLine { source: &'a Source, line: usize },
-    Highlight { span: Span, message: &'a String },
+
+    /// A line of code, with its line number in the gutter.
+    CodeLine {
+        /// The source the line is in.
+        source: &'a Source,
+
+        /// The line number, as shown in the gutter.
+        line: usize,
+    },
+
+    /// The underline of part of the code line above it.
+    Highlight {
+        /// The part of the line that is underlined.
+        span: Span,
+
+        /// The message shown along with the underline, which may be empty.
+        message: &'a str,
+    },
+
+    /// A note below all the code.
+    Note(&'a str),
 }
 
 impl<'a> DiagnosticRenderLine<'a> {
@@ -112,21 +237,166 @@ impl<'a> DiagnosticRenderLine<'a> {
             DRL::Padding => 0,
             DRL::CodeLine { line, .. } => (line.ilog10() + 1) as usize,
             DRL::Highlight { .. } => 0,
+            DRL::Note(_) => 0,
+        }
+    }
+}
+
+/// Whether diagnostics are colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Use colors if stdout is a terminal, unless the `NO_COLOR` environment
+    /// variable is set to something other than an empty string.
+    #[default]
+    Auto,
+
+    /// Always use colors, even when stdout isn't a terminal.
+    Always,
+
+    /// Never use colors.
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether this choice means the output should be styled with ANSI escape
+    /// sequences in the current environment.
+    pub fn use_ansi(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => auto_use_ansi(
+                env::var_os("NO_COLOR").as_deref(),
+                io::stdout().is_terminal(),
+            ),
         }
     }
 }
 
+/// Whether `ColorChoice::Auto` uses colors, given the value of `NO_COLOR` and
+/// whether stdout is a terminal. Any non-empty `NO_COLOR` turns them off.
+fn auto_use_ansi(no_color: Option<&OsStr>, is_terminal: bool) -> bool {
+    let no_color = no_color.is_some_and(|v| !v.is_empty());
+    !no_color && is_terminal
+}
+
+/// Options for how diagnostics are presented.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Whether to use colors and other escape sequences.
+    pub color: ColorChoice,
+
+    /// Whether to make the `-->` location a clickable OSC 8 hyperlink to the
+    /// file. Only some terminals support these.
+    pub hyperlinks: bool,
+
+    /// Help messages longer than this many characters are put on their own
+    /// line below the carets, aligned with the start of the span, instead of
+    /// directly after them. If `None`, messages always go after the carets.
+    pub inline_label_width: Option<usize>,
+
+    /// The number of spaces each tab in a line of code is shown as. Tabs are
+    /// expanded so the carets below the line can be aligned with it.
+    pub tab_width: usize,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            color: ColorChoice::default(),
+            hyperlinks: false,
+            inline_label_width: None,
+            tab_width: 4,
+        }
+    }
+}
+
+/// Writes the lines of rendered diagnostics into `wr`.
 pub struct DiagWriter<'a, W: Write> {
+    /// Where the rendered text goes.
     wr: &'a mut W,
+
+    /// How the diagnostics are presented.
+    theme: Theme,
+
+    /// Whether to style the output with ANSI escape sequences, as decided by
+    /// the theme's `ColorChoice`. Plain output is meant for places that
+    /// aren't a terminal, like files.
+    ansi: bool,
 }
 
 impl<'a, W: Write> DiagWriter<'a, W> {
+    /// A writer that always uses colors.
     pub fn new_ansi(wr: &'a mut W) -> Self {
-        Self { wr }
+        let theme = Theme {
+            color: ColorChoice::Always,
+            ..Theme::default()
+        };
+        Self::with_theme(wr, theme)
+    }
+
+    /// A writer that doesn't use colors or any other escape sequences.
+    pub fn new_plain(wr: &'a mut W) -> Self {
+        let theme = Theme {
+            color: ColorChoice::Never,
+            ..Theme::default()
+        };
+        Self::with_theme(wr, theme)
     }
 
-    fn write_error(&mut self, msg: &str) -> Result<(), fmt::Error> {
-        writeln!(self.wr, "{RED_FG}{BOLD}error{RESET}{BOLD}: {msg}{RESET}")
+    /// A writer presenting diagnostics as `theme` says.
+    pub fn with_theme(wr: &'a mut W, theme: Theme) -> Self {
+        Self {
+            wr,
+            theme,
+            ansi: theme.color.use_ansi(),
+        }
+    }
+
+    /// `text` with its tabs replaced by spaces, as it is shown in a code line.
+    fn expand_tabs(&self, text: &str) -> String {
+        text.replace('\t', &" ".repeat(self.theme.tab_width))
+    }
+
+    /// The escape sequences to style the output with, which are empty for plain
+    /// output.
+    fn palette(&self) -> Palette {
+        if self.ansi {
+            ANSI_PALETTE
+        } else {
+            PLAIN_PALETTE
+        }
+    }
+
+    /// The color the label and underlines of a diagnostic with `severity`
+    /// are drawn in. Notes are only bold.
+    fn severity_color(&self, severity: Severity) -> &'static str {
+        let palette = self.palette();
+
+        match severity {
+            Severity::Error => palette.red,
+            Severity::Warning => palette.yellow,
+            Severity::Note => "",
+        }
+    }
+
+    /// Write the first line of a diagnostic, with its severity and code and
+    /// message.
+    fn write_header(
+        &mut self,
+        severity: Severity,
+        code: Option<&str>,
+        msg: &str,
+    ) -> Result<(), fmt::Error> {
+        let Palette { bold, reset, .. } = self.palette();
+        let color = self.severity_color(severity);
+        let label = severity.label();
+
+        let code = code.map(|code| format!("[{code}]")).unwrap_or_default();
+
+        writeln!(
+            self.wr,
+            "{color}{bold}{label}{code}{reset}{bold}: {msg}{reset}"
+        )
     }
 
     fn write_source_pos(
@@ -135,52 +405,442 @@ impl<'a, W: Write> DiagWriter<'a, W> {
         source: &Source,
         gw: usize,
     ) -> Result<(), fmt::Error> {
-        writeln!(
-            self.wr,
-            "{}{BLUE_FG}{BOLD}-->{RESET} {}:{}:{}",
-            " ".repeat(gw),
+        let location = format!(
+            "{}:{}:{}",
             source.filename(),
             source.line_of(pos),
             source.col_of(pos)
-        )
+        );
+
+        let Palette {
+            bold, blue, reset, ..
+        } = self.palette();
+        write!(self.wr, "{}{blue}{bold}-->{reset} ", " ".repeat(gw))?;
+
+        // Only link to sources loaded from disk. Hyperlinks are escape
+        // sequences too, so plain output never has them.
+        let path = source.path().filter(|_| self.ansi && self.theme.hyperlinks);
+
+        match path {
+            Some(path) => {
+                let url = file_url(path);
+                writeln!(self.wr, "{OSC8}{url}{ST}{location}{OSC8}{ST}")
+            }
+            None => writeln!(self.wr, "{location}"),
+        }
     }
 
     fn write_padding(&mut self, gw: usize) -> Result<(), fmt::Error> {
-        writeln!(self.wr, "{}{BLUE_FG}{BOLD} |{RESET}", " ".repeat(gw))
+        let Palette {
+            bold, blue, reset, ..
+        } = self.palette();
+        writeln!(self.wr, "{}{blue}{bold} |{reset}", " ".repeat(gw))
     }
 
-    fn write_code(&mut self, source: &Source, line: usize, gw: usize) -> Result<(), fmt::Error> {
-        let line_span = source.span_of_line(line);
-        let text = source.text_of_span(line_span);
+    /// Write a note below the code, aligned with the gutter.
+    fn write_note(&mut self, gw: usize, note: &str) -> Result<(), fmt::Error> {
+        let Palette {
+            bold, blue, reset, ..
+        } = self.palette();
         writeln!(
             self.wr,
-            "{BLUE_FG}{BOLD}{0:1$} |{RESET} {2}",
-            line, gw, text
+            "{}{blue}{bold} ={reset} {bold}note{reset}: {note}",
+            " ".repeat(gw)
         )
     }
 
+    fn write_code(&mut self, source: &Source, line: usize, gw: usize) -> Result<(), fmt::Error> {
+        let line_span = source.span_of_line(line);
+        let text = self.expand_tabs(source.text_of_span(line_span));
+        let Palette {
+            bold, blue, reset, ..
+        } = self.palette();
+        writeln!(self.wr, "{blue}{bold}{0:1$} |{reset} {2}", line, gw, text)
+    }
+
     fn write_highlight(
         &mut self,
+        severity: Severity,
         source: &Source,
         span: Span,
         gw: usize,
         msg: &str,
     ) -> Result<(), fmt::Error> {
-        assert!(source.line_of(span.start()) == source.line_of(span.end()));
-
-        let offset = source.col_of(span.start()) - 1;
-
-        // TODO: Actual text length.
-        let len = source.text_of_span(span).chars().count();
-        let highlight_text = "^".repeat(len);
+        // Measure in terminal columns rather than chars, so the carets stay
+        // under the code when it has wide or zero width characters.
+        let line_start = source.span_of_line(source.line_of(span.start())).start();
+        let before = source.text_of_span(Span::new(line_start, span.start()));
+        let offset = self.expand_tabs(before).width();
+
+        // An empty span points between two characters, which we show with a
+        // single caret under the second.
+        let first_line = source.text_of_span(span).lines().next().unwrap_or_default();
+        let highlight_text = "^".repeat(self.expand_tabs(first_line).width().max(1));
+
+        let Palette {
+            bold, blue, reset, ..
+        } = self.palette();
+        let color = self.severity_color(severity);
+
+        let wrap = self
+            .theme
+            .inline_label_width
+            .is_some_and(|w| msg.chars().count() > w);
+        if !wrap {
+            // Lines of a multi-line span other than the last have no message.
+            let separator = if msg.is_empty() { "" } else { " " };
+            return writeln!(
+                self.wr,
+                "{}{blue}{bold} | {reset}{}{color}{bold}{}{separator}{}{reset}",
+                " ".repeat(gw),
+                " ".repeat(offset),
+                highlight_text,
+                msg
+            );
+        }
 
         writeln!(
             self.wr,
-            "{}{BLUE_FG}{BOLD} | {RESET}{}{RED_FG}{BOLD}{} {}{RESET}",
+            "{}{blue}{bold} | {reset}{}{color}{bold}{}{reset}",
             " ".repeat(gw),
             " ".repeat(offset),
             highlight_text,
+        )?;
+        writeln!(
+            self.wr,
+            "{}{blue}{bold} | {reset}{}{color}{bold}{}{reset}",
+            " ".repeat(gw),
+            " ".repeat(offset),
             msg
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostics::ColorChoice, testing::strip_ansi};
+    use std::fs;
+
+    #[test]
+    fn file_url_is_percent_encoded() {
+        let url = file_url(Path::new("/tmp/my dir/100%/é.fern"));
+        assert_eq!(url, "file:///tmp/my%20dir/100%25/%C3%A9.fern");
+    }
+
+    #[test]
+    fn hyperlinks_to_loaded_file() {
+        let dir = env::temp_dir().join(format!("fernc hyperlink {}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a b.fern");
+        fs::write(&file, "fn main() {}\n").unwrap();
+
+        let mut sm = SourceMap::new();
+        let id = sm.add_source_from_file(file.to_str().unwrap()).unwrap();
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("oops".to_owned()).add_part(source.span(0, 2), String::new());
+
+        // Removing the file doesn't affect the rendering, since the path was
+        // resolved when the source was loaded.
+        fs::remove_dir_all(&dir).unwrap();
+
+        let theme = Theme {
+            color: ColorChoice::Always,
+            hyperlinks: true,
+            ..Theme::default()
+        };
+        let mut out = String::new();
+        diag.render_with_theme(&mut out, &sm, theme).unwrap();
+
+        let url = file_url(source.path().unwrap());
+        assert!(url.ends_with("a%20b.fern"));
+        assert!(out.contains(&format!("{OSC8}{url}{ST}")));
+        assert!(!strip_ansi(&out).contains("file://"));
+    }
+
+    #[test]
+    fn long_labels_wrap_under_the_span() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = y;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `y`".to_owned())
+            .add_part(source.span(4, 5), "unused".to_owned())
+            .add_part(
+                source.span(8, 9),
+                "variables must be declared before they are used".to_owned(),
+            );
+
+        let theme = Theme {
+            color: ColorChoice::Never,
+            inline_label_width: Some(20),
+            ..Theme::default()
+        };
+        let mut out = String::new();
+        diag.render_with_theme(&mut out, &sm, theme).unwrap();
+
+        let expected = "\
+error: unknown variable `y`
+ --> test.fern:1:5
+  |
+1 | let x = y;
+  |     ^ unused
+  |         ^
+  |         variables must be declared before they are used
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn plain_rendering() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "fn f() { x }\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `x`".to_owned())
+            .add_part(source.span(9, 10), "not found".to_owned());
+
+        let mut out = String::new();
+        diag.render_plain(&mut out, &sm).unwrap();
+
+        let expected = "\
+error: unknown variable `x`
+ --> test.fern:1:10
+  |
+1 | fn f() { x }
+  |          ^ not found
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn color_choice() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "x\n".to_owned());
+        let diag = Diagnostic::new("oops".to_owned())
+            .add_part(sm.get_source(id).span(0, 1), String::new());
+        let render = |color| {
+            let theme = Theme {
+                color,
+                ..Theme::default()
+            };
+            let mut out = String::new();
+            diag.render_with_theme(&mut out, &sm, theme).unwrap();
+            out
+        };
+
+        let never = render(ColorChoice::Never);
+        assert!(!never.contains('\x1b'), "{never:?}");
+
+        // Even though a string isn't a terminal.
+        let always = render(ColorChoice::Always);
+        assert!(
+            always.starts_with(&format!("{RED_FG}{BOLD}error")),
+            "{always:?}"
+        );
+        assert_eq!(strip_ansi(&always), never);
+
+        // `NO_COLOR` turns colors off even on a terminal, unless it is empty.
+        assert!(auto_use_ansi(None, true));
+        assert!(!auto_use_ansi(Some(OsStr::new("1")), true));
+        assert!(auto_use_ansi(Some(OsStr::new("")), true));
+        assert!(!auto_use_ansi(None, false));
+    }
+
+    #[test]
+    fn multi_line_span() {
+        let mut sm = SourceMap::new();
+        let text = "let x = f(1,\n    2);\n";
+        let id = sm.add_source("test.fern".to_owned(), text.to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("bad call".to_owned())
+            .add_part(source.span(8, 20), "this call".to_owned());
+
+        // Each line is underlined, and the message goes on the last one.
+        let expected = "\
+error: bad call
+ --> test.fern:1:9
+  |
+1 | let x = f(1,
+  |         ^^^^
+2 |     2);
+  | ^^^^^^^ this call
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+
+    #[test]
+    fn highlights_on_one_line() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = a + b;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("mismatched types".to_owned())
+            .add_part(source.span(12, 13), "this is a bool".to_owned())
+            .add_part(source.span(8, 9), "this is an int".to_owned());
+
+        // The line is shown once, with the carets in the order of their
+        // columns.
+        let expected = "\
+error: mismatched types
+ --> test.fern:1:9
+  |
+1 | let x = a + b;
+  |         ^ this is an int
+  |             ^ this is a bool
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+
+    #[test]
+    fn carets_after_wide_chars() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let 名前 = x;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `x`".to_owned())
+            .add_part(source.span(4, 10), "two columns each".to_owned())
+            .add_part(source.span(13, 14), "not found".to_owned());
+
+        // Each of the characters in `名前` takes up two columns.
+        let expected = "\
+error: unknown variable `x`
+ --> test.fern:1:5
+  |
+1 | let 名前 = x;
+  |     ^^^^ two columns each
+  |            ^ not found
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+
+    #[test]
+    fn carets_after_tabs() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "\tlet y = z;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("unknown variable `z`".to_owned())
+            .add_part(source.span(9, 10), "not found".to_owned());
+
+        let expected = "\
+error: unknown variable `z`
+ --> test.fern:1:10
+  |
+1 |     let y = z;
+  |             ^ not found
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+
+        // The code and the offset of the carets are expanded the same way.
+        let theme = Theme {
+            color: ColorChoice::Never,
+            tab_width: 2,
+            ..Theme::default()
+        };
+        let mut out = String::new();
+        diag.render_with_theme(&mut out, &sm, theme).unwrap();
+        assert!(
+            out.contains("1 |   let y = z;\n  |           ^ not found\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn severity_label_and_color() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let x = 1;\n".to_owned());
+        let span = sm.get_source(id).span(4, 5);
+        let theme = Theme {
+            color: ColorChoice::Always,
+            ..Theme::default()
+        };
+
+        // Notes are only bold.
+        let cases = [
+            (Severity::Warning, YELLOW_FG, "warning"),
+            (Severity::Note, "", "note"),
+        ];
+        for (severity, color, label) in cases {
+            let diag = Diagnostic::new("unused variable `x`".to_owned())
+                .with_severity(severity)
+                .add_part(span, String::new());
+
+            let mut out = String::new();
+            diag.render_with_theme(&mut out, &sm, theme).unwrap();
+            assert!(
+                out.starts_with(&format!("{color}{BOLD}{label}{RESET}")),
+                "{out:?}"
+            );
+
+            let plain = diag.render_plain_string(&sm);
+            assert!(plain.starts_with(&format!("{label}: ")), "{plain}");
+        }
+    }
+
+    #[test]
+    fn notes_after_code() {
+        let mut sm = SourceMap::new();
+        let text = "fn main() {}\nfn main() {}\n";
+        let id = sm.add_source("test.fern".to_owned(), text.to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("`main` is defined twice".to_owned())
+            .add_note("functions can't be overloaded".to_owned())
+            .add_part(source.span(16, 20), "redefined here".to_owned())
+            .add_note("rename one of them".to_owned());
+
+        // The notes keep their order, and come after all of the code even if
+        // they were added before a part.
+        let expected = "\
+error: `main` is defined twice
+ --> test.fern:2:4
+  |
+2 | fn main() {}
+  |    ^^^^ redefined here
+  |
+  = note: functions can't be overloaded
+  = note: rename one of them
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+
+    #[test]
+    fn crlf_code_lines() {
+        let mut sm = SourceMap::new();
+        let text = "let x = 1;\r\nlet y = x z;\r\n";
+        let id = sm.add_source("test.fern".to_owned(), text.to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("expected `;`".to_owned())
+            .add_part(source.span(22, 23), "here".to_owned())
+            .add_part(source.span(4, 5), String::new());
+
+        let expected = "\
+error: expected `;`
+ --> test.fern:1:5
+  |
+1 | let x = 1;
+  |     ^
+2 | let y = x z;
+  |           ^ here
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+
+        // The "\r\n" ending a line is one column, like a "\n" would be.
+        let col = |byte| source.col_of(source.pos_at(byte).unwrap());
+        assert_eq!((col(10), col(11), col(12)), (11, 11, 1));
+    }
+
+    #[test]
+    fn point_span_at_end_of_file() {
+        let mut sm = SourceMap::new();
+        let text = "let x = 1";
+        let id = sm.add_source("test.fern".to_owned(), text.to_owned());
+        let source = sm.get_source(id);
+        let diag = Diagnostic::new("expected `;`".to_owned())
+            .add_part(source.point_span(text.len()), "add a `;` here".to_owned());
+
+        let expected = "\
+error: expected `;`
+ --> test.fern:1:10
+  |
+1 | let x = 1
+  |          ^ add a `;` here
+";
+        assert_eq!(diag.render_plain_string(&sm), expected);
+    }
+}
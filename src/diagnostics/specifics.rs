@@ -1,30 +1,74 @@
+/// Problems with how the compiler was invoked, such as with a file that can't
+/// be read.
+pub mod driver {
+    use std::io;
+
+    use super::super::Diagnostic;
+
+    /// The file `filename` couldn't be loaded because of `error`.
+    pub fn unreadable_file(filename: &str, error: &io::Error) -> Diagnostic {
+        Diagnostic::new(format!("Couldn't read file `{filename}`: {error}."))
+    }
+
+    /// `--emit` was given a `value` that isn't one of the things we can print.
+    pub fn unknown_emit(value: &str) -> Diagnostic {
+        Diagnostic::new(format!(
+            "Unknown value `{value}` for `--emit`, expected `tokens` or `ast`."
+        ))
+    }
+}
+
+/// Problems found while lexing, which all have error codes starting at `E0001`.
 pub mod lex {
     use std::fmt::format;
 
     use super::super::Diagnostic;
     use crate::source_map::{Source, Span};
 
+    /// `span` may cover a run of several illegal characters.
     pub fn illegal_char(span: Span, source: &Source) -> Diagnostic {
         let sym_text = source.text_of_span(span);
+        let noun = if sym_text.chars().count() > 1 {
+            "characters"
+        } else {
+            "character"
+        };
 
-        Diagnostic::new(format!("Illegal character `{sym_text}`.")) //
+        Diagnostic::new(format!("Illegal {noun} `{sym_text}`."))
+            .with_code("E0001".to_owned())
             .add_part(span, String::new())
     }
 
+    /// The delimiter at `span` is never closed.
     pub fn unmatched_open_paren(span: Span, source: &Source) -> Diagnostic {
         let paren_text = source.text_of_span(span);
+        let close_text = match paren_text {
+            "(" => ")",
+            "[" => "]",
+            _ => "}",
+        };
 
-        Diagnostic::new(format!("Unclosed delimiter `{paren_text}`."))
-            .add_part(span, "has no match".to_owned())
+        Diagnostic::new(format!("This `{paren_text}` is never closed."))
+            .with_code("E0002".to_owned())
+            .add_part(span, format!("no matching `{close_text}`"))
+    }
+
+    /// The delimiter at `span` is still open at the end of the file.
+    pub fn unclosed_at_eof(span: Span, source: &Source) -> Diagnostic {
+        unmatched_open_paren(span, source).add_note("reached the end of the file".to_owned())
     }
 
+    /// The closing delimiter at `span` doesn't close anything.
     pub fn unmatched_close_paren(span: Span, source: &Source) -> Diagnostic {
         let paren_text = source.text_of_span(span);
 
         Diagnostic::new(format!("Unexpected closing delimiter `{paren_text}`."))
+            .with_code("E0003".to_owned())
             .add_part(span, "has no match".to_owned())
     }
 
+    /// The delimiter at `close_span` closes the one at `open_span`, but they
+    /// don't match.
     pub fn mismatched_close_paren(
         open_span: Span,
         close_span: Span,
@@ -33,7 +77,351 @@ pub mod lex {
         let close_text = source.text_of_span(close_span);
 
         Diagnostic::new(format!("Mismatched closing delimiter `{close_text}`."))
+            .with_code("E0004".to_owned())
             .add_part(open_span, "unclosed delimiter".to_owned())
             .add_part(close_span, "mismatched closing delimiter".to_owned())
     }
+
+    /// The string literal starting at `span` has no closing quote.
+    pub fn unterminated_string(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Unterminated string literal.".to_owned())
+            .with_code("E0005".to_owned())
+            .add_part(
+                span,
+                "no closing quote before the end of the file".to_owned(),
+            )
+    }
+
+    /// The escape sequence at `escape_span` in a string or character literal
+    /// isn't one we know.
+    pub fn invalid_escape(escape_span: Span, source: &Source) -> Diagnostic {
+        let escape_text = source.text_of_span(escape_span);
+
+        Diagnostic::new(format!("Unknown escape sequence `{escape_text}`."))
+            .with_code("E0006".to_owned())
+            .add_part(escape_span, "unknown escape".to_owned())
+    }
+
+    /// `span` covers the whole comment, but we only point at its opening
+    /// `/*` since the rest goes all the way to the end of the file.
+    pub fn unterminated_block_comment(span: Span, source: &Source) -> Diagnostic {
+        let open_span = source.span_with_len(span.start().byte(), "/*".len());
+
+        Diagnostic::new("Unterminated block comment.".to_owned())
+            .with_code("E0007".to_owned())
+            .add_part(open_span, "this comment is never closed".to_owned())
+    }
+
+    /// The character literal at `span` has no closing quote on its line.
+    pub fn unterminated_char_lit(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Unterminated character literal.".to_owned())
+            .with_code("E0008".to_owned())
+            .add_part(span, "no closing quote on this line".to_owned())
+    }
+
+    /// The character literal at `span` is just `''`.
+    pub fn empty_char_lit(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Empty character literal.".to_owned())
+            .with_code("E0009".to_owned())
+            .add_part(span, "expected a character".to_owned())
+    }
+
+    /// The character literal at `span` contains more than one character.
+    pub fn char_lit_too_long(span: Span, source: &Source) -> Diagnostic {
+        let lit_text = source.text_of_span(span);
+
+        Diagnostic::new(format!(
+            "Character literal `{lit_text}` has more than one character."
+        ))
+        .with_code("E0010".to_owned())
+        .add_part(span, "use double quotes for a string".to_owned())
+    }
+
+    /// The digit at `digit_span` isn't valid in the base of the number literal
+    /// at `span`, which has a `0x`, `0o` or `0b` prefix.
+    pub fn invalid_digit_for_base(span: Span, digit_span: Span, source: &Source) -> Diagnostic {
+        let digit_text = source.text_of_span(digit_span);
+        let base_name = match source.text_of_span(span).get(..2) {
+            Some("0x") => "hexadecimal",
+            Some("0o") => "octal",
+            _ => "binary",
+        };
+
+        Diagnostic::new(format!(
+            "Invalid digit `{digit_text}` in a {base_name} literal."
+        ))
+        .with_code("E0011".to_owned())
+        .add_part(digit_span, format!("not a {base_name} digit"))
+    }
+
+    /// The number literal at `span` has an `_` that isn't between two digits.
+    pub fn malformed_number(span: Span, source: &Source) -> Diagnostic {
+        let lit_text = source.text_of_span(span);
+
+        Diagnostic::new(format!("Malformed number `{lit_text}`."))
+            .with_code("E0012".to_owned())
+            .add_part(span, "`_` can only be used between digits".to_owned())
+    }
+
+    /// The integer literal at `span` doesn't fit in a `u64`.
+    pub fn int_literal_too_large(span: Span, source: &Source) -> Diagnostic {
+        let lit_text = source.text_of_span(span);
+
+        Diagnostic::new(format!("Integer literal `{lit_text}` is too large."))
+            .with_code("E0013".to_owned())
+            .add_part(span, format!("doesn't fit in {} bits", u64::BITS))
+    }
+
+    /// The integer literal at `span` can't be parsed for some other reason.
+    pub fn malformed_int_literal(span: Span, source: &Source) -> Diagnostic {
+        let lit_text = source.text_of_span(span);
+
+        Diagnostic::new(format!("Malformed integer literal `{lit_text}`."))
+            .with_code("E0014".to_owned())
+            .add_part(span, String::new())
+    }
+
+    /// The delimiter at `span` is nested inside too many others, so the lexer
+    /// skips its contents.
+    pub fn nesting_too_deep(span: Span, source: &Source) -> Diagnostic {
+        let paren_text = source.text_of_span(span);
+
+        Diagnostic::new(format!("This `{paren_text}` is nested too deeply."))
+            .with_code("E0015".to_owned())
+            .add_part(span, "its contents are skipped".to_owned())
+    }
+}
+
+/// Problems found while parsing.
+pub mod parse {
+    use super::super::Diagnostic;
+    use crate::{
+        lex::{
+            similar_keyword,
+            token::{TokenTree, TokenType},
+        },
+        source_map::{Source, Span},
+    };
+
+    /// How to refer to `token` in a message. Nested tokens are referred to
+    /// by their opening delimiter rather than their whole text.
+    fn token_text<'a>(token: &TokenTree, source: &'a Source) -> &'a str {
+        let text = source.text_of_span(token.span());
+
+        if token.ty().is_nested() {
+            &text[..1]
+        } else {
+            text
+        }
+    }
+
+    /// The keywords a declaration can start with, which are suggested when a
+    /// declaration is expected.
+    const DECLARATION_KEYWORDS: &[&str] = &["fn", "struct", "enum", "impl"];
+
+    /// A declaration was expected where `found` is. If it is a misspelled
+    /// declaration keyword, that keyword is suggested.
+    pub fn expected_declaration(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        let suggestion = (found.ty() == TokenType::Ident)
+            .then(|| similar_keyword(found_text, DECLARATION_KEYWORDS))
+            .flatten();
+        let help = match suggestion {
+            Some(keyword) => format!("did you mean `{keyword}`?"),
+            None => "expected a declaration".to_owned(),
+        };
+
+        Diagnostic::new(format!("Expected a declaration, found `{found_text}`."))
+            .add_part(found.span(), help)
+    }
+
+    /// A token of type `expected` was expected where `found` is.
+    pub fn unexpected_token(expected: TokenType, found: &TokenTree, source: &Source) -> Diagnostic {
+        let expected = expected.describe();
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected {expected}, found `{found_text}`."))
+            .add_part(found.span(), format!("expected {expected}"))
+    }
+
+    /// The tokens ran out after `prev`, at the end of the file or of the
+    /// enclosing delimiters, where `expected` should have been.
+    pub fn unexpected_end(expected: &str, prev: Span, source: &Source) -> Diagnostic {
+        let end = prev.end().byte();
+
+        Diagnostic::new(format!("Expected {expected}."))
+            .add_part(source.point_span(end), format!("expected {expected}"))
+    }
+
+    /// The name of the variable was expected after the `let` at `let_kw`, where
+    /// `found` is, if there is anything.
+    pub fn expected_let_name(
+        let_kw: Span,
+        found: Option<&TokenTree>,
+        source: &Source,
+    ) -> Diagnostic {
+        expected_variable_name("let", let_kw, found, source)
+    }
+
+    /// The loop variable was expected after the `for` at `for_kw`, where
+    /// `found` is, if there is anything.
+    pub fn expected_loop_variable(
+        for_kw: Span,
+        found: Option<&TokenTree>,
+        source: &Source,
+    ) -> Diagnostic {
+        expected_variable_name("for", for_kw, found, source)
+    }
+
+    /// A variable name was expected after the keyword `kw`.
+    fn expected_variable_name(
+        kw: &str,
+        kw_span: Span,
+        found: Option<&TokenTree>,
+        source: &Source,
+    ) -> Diagnostic {
+        let help = "expected a variable name".to_owned();
+
+        match found {
+            Some(found) => {
+                let found_text = token_text(found, source);
+                Diagnostic::new(format!("Expected a variable name, found `{found_text}`."))
+                    .add_part(found.span(), help)
+            }
+            None => {
+                let end = kw_span.end().byte();
+                Diagnostic::new(format!("Expected a variable name after `{kw}`."))
+                    .add_part(source.point_span(end), help)
+            }
+        }
+    }
+
+    /// An expression was expected where `found` is.
+    pub fn expected_expression(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected an expression, found `{found_text}`."))
+            .add_part(found.span(), "expected an expression".to_owned())
+    }
+
+    /// The parentheses at `span` don't contain an expression.
+    pub fn empty_parens(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Expected an expression inside the parentheses.".to_owned())
+            .add_part(span, "expected an expression".to_owned())
+    }
+
+    /// The parenthesized expression should have ended where `found` is.
+    pub fn expected_close_paren(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected `)`, found `{found_text}`."))
+            .add_part(found.span(), "expected `)`".to_owned())
+    }
+
+    /// Another argument or the end of the arguments was expected where `found`
+    /// is.
+    pub fn expected_comma_or_close_paren(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected `,` or `)`, found `{found_text}`."))
+            .add_part(found.span(), "expected `,` or `)`".to_owned())
+    }
+
+    /// There is no field name after the `.` of a field access.
+    pub fn expected_field_name(dot: &TokenTree, source: &Source) -> Diagnostic {
+        let end = dot.span().end().byte();
+
+        Diagnostic::new("Expected a field name after `.`.".to_owned())
+            .add_part(source.point_span(end), "expected a field name".to_owned())
+    }
+
+    /// The comparison at `second_op` directly compares the result of the one at
+    /// `first_op`, as in `a < b < c`.
+    pub fn chained_comparison(first_op: Span, second_op: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Comparison operators can't be chained.".to_owned())
+            .add_part(first_op, String::new())
+            .add_part(second_op, "use `&&` to combine the comparisons".to_owned())
+    }
+
+    /// The operator `op` isn't followed by its operand.
+    pub fn expected_expression_after(op: &TokenTree, source: &Source) -> Diagnostic {
+        let op_text = token_text(op, source);
+        let end = op.span().end().byte();
+
+        Diagnostic::new(format!("Expected an expression after `{op_text}`."))
+            .add_part(source.point_span(end), "expected an expression".to_owned())
+    }
+
+    /// The expression or type at `span` is nested too deeply to parse without
+    /// running out of stack.
+    pub fn nesting_too_deep(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("This is nested too deeply.".to_owned())
+            .add_part(span, "the parser gives up here".to_owned())
+    }
+
+    /// A type was expected where `found` is.
+    pub fn expected_type(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected a type, found `{found_text}`."))
+            .add_part(found.span(), "expected a type".to_owned())
+    }
+
+    /// The brackets of the type at `span` don't contain a type.
+    pub fn empty_type_brackets(span: Span, _source: &Source) -> Diagnostic {
+        Diagnostic::new("Expected a type inside the brackets.".to_owned())
+            .add_part(span, "expected a type".to_owned())
+    }
+
+    /// The type in brackets should have ended where `found` is.
+    pub fn expected_close_bracket(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected `]`, found `{found_text}`."))
+            .add_part(found.span(), "expected `]`".to_owned())
+    }
+
+    /// Another type argument or the end of the arguments was expected where
+    /// `found` is.
+    pub fn expected_comma_or_close_bracket(found: &TokenTree, source: &Source) -> Diagnostic {
+        let found_text = token_text(found, source);
+
+        Diagnostic::new(format!("Expected `,` or `]`, found `{found_text}`."))
+            .add_part(found.span(), "expected `,` or `]`".to_owned())
+    }
+}
+
+/// Problems found by the passes over the AST.
+pub mod passes {
+    use super::super::{Diagnostic, Severity};
+    use crate::source_map::Span;
+
+    /// The expression at `span` divides by a constant zero.
+    pub fn division_by_zero(span: Span) -> Diagnostic {
+        Diagnostic::new("This divides by zero.".to_owned())
+            .with_severity(Severity::Warning)
+            .add_part(span, "this is always zero".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+
+    #[test]
+    fn illegal_char_has_code() {
+        let mut sm = SourceMap::new();
+        let id = sm.add_source("test.fern".to_owned(), "let @ = 1;\n".to_owned());
+        let source = sm.get_source(id);
+        let diag = lex::illegal_char(source.span(4, 5), source);
+
+        assert_eq!(diag.code.as_deref(), Some("E0001"));
+        let rendered = diag.render_plain_string(&sm);
+        assert!(
+            rendered.starts_with("error[E0001]: Illegal character `@`."),
+            "{rendered}"
+        );
+    }
 }
@@ -0,0 +1,282 @@
+//! The driver runs the compiler stages over a whole set of files.
+
+use crate::{
+    diagnostics::{render_all, specifics::driver::unreadable_file, Diagnostics},
+    parse::{ast::FileAst, parse_source},
+    passes::const_fold::const_fold_file,
+    source_map::{SourceId, SourceMap},
+};
+use std::io;
+
+/// Everything produced by compiling a set of files.
+#[derive(Debug)]
+pub struct CompileResult {
+    /// The `SourceMap` holding every file that could be loaded.
+    pub source_map: SourceMap,
+
+    /// The files that parsed successfully along with the id of their `Source`.
+    pub files: Vec<(SourceId, FileAst)>,
+
+    /// The diagnostics produced by any of the files, in file order. Files
+    /// that parsed can still have warnings from the passes run over them.
+    pub diagnostics: Diagnostics,
+}
+
+impl CompileResult {
+    /// Whether any file failed to load or compile. Warnings don't count.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.error_count() > 0
+    }
+}
+
+/// Load and compile all of the files in `paths`, continuing past any that
+/// fail.
+///
+/// A file that can't be read produces a diagnostic rather than aborting the
+/// whole compilation.
+pub fn compile_all(paths: &[&str]) -> CompileResult {
+    let mut source_map = SourceMap::new();
+    let mut diagnostics = Diagnostics::new();
+
+    for path in paths {
+        if let Err(e) = source_map.add_source_from_file(path) {
+            diagnostics.push(unreadable_file(path, &e));
+        }
+    }
+
+    compile_sources(source_map, diagnostics)
+}
+
+/// Compile `text` as a single source and render all of its diagnostics.
+///
+/// No input should be able to make this panic, which makes it a convenient
+/// entry point for fuzzing. Returns an error if the diagnostics couldn't be
+/// rendered.
+pub fn try_compile(text: &str) -> Result<CompileResult, String> {
+    let mut source_map = SourceMap::new();
+    source_map.add_source(String::from("<input>"), text.to_owned());
+
+    let result = compile_sources(source_map, Diagnostics::new());
+
+    // The rendered text isn't needed, only that rendering succeeds.
+    render_all(&result.diagnostics, &mut io::sink(), &result.source_map)
+        .map_err(|e| format!("Couldn't render the diagnostics: {e}"))?;
+
+    Ok(result)
+}
+
+/// Compile every source in `source_map`, adding to the `diagnostics` already
+/// reported while loading them.
+fn compile_sources(source_map: SourceMap, mut diagnostics: Diagnostics) -> CompileResult {
+    let mut files = Vec::new();
+
+    for source in source_map.sources() {
+        match parse_source(source) {
+            Ok(mut file) => {
+                const_fold_file(&mut file, &mut diagnostics);
+                files.push((source.id(), file));
+            }
+            Err(e) => diagnostics.extend(e),
+        }
+    }
+
+    CompileResult {
+        source_map,
+        files,
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::MAX_TREE_DEPTH;
+
+    /// Inputs that have broken the compiler before or are likely to.
+    const NASTY_INPUTS: &[&str] = &[
+        "",
+        "fn",
+        "fn f",
+        "fn f(",
+        "fn f()",
+        "fn f() {",
+        "fn f() }",
+        "fn f() { let",
+        "fn f() { let x",
+        "fn f() { let x: }",
+        "fn f() { if }",
+        "fn f() { if x { } else }",
+        "fn f() { while }",
+        "fn f() { for }",
+        "fn f() { for i in }",
+        "fn f() { return }",
+        "fn f() { a. }",
+        "fn f() { a:: }",
+        "fn f() { f(,) }",
+        "fn f(a: &) {}",
+        "fn f(a: [) {}",
+        "fn f(a: Vec[]) {}",
+        "fn f(a: fn() ->) {}",
+        "fn f() -> {}",
+        "fn f() { (]",
+        ")))",
+        "]]]",
+        "}}}",
+        "((([[[{{{",
+        "fn f() { \"",
+        "fn f() { '",
+        "fn f() { /*",
+        "fn f() { 0x }",
+        "fn f() { 99999999999999999999999 }",
+        "\u{0}\u{7f}\u{1b}[31m",
+        "\r\r\n\t",
+        "fn é() { ü }",
+    ];
+
+    /// Text where `open` is repeated `n` times, followed by `middle` and then
+    /// `close` repeated `n` times.
+    fn nest(open: &str, middle: &str, close: &str, n: usize) -> String {
+        format!("{}{middle}{}", open.repeat(n), close.repeat(n))
+    }
+
+    /// Deeply nested inputs, which must neither overflow the stack nor take
+    /// forever.
+    fn deep_inputs() -> Vec<String> {
+        let n = 10_000;
+
+        vec![
+            nest("fn f() { ", "", "}", n),
+            nest("fn f() { -", "1", "", n) + " }",
+            nest("fn f() { !", "x", "", n) + " }",
+            nest("fn f() { (", "1", ")", n) + " }",
+            nest("fn f() { [", "", "]", n),
+            format!("fn f() {{ {} }}", vec!["x"; n].join(" + ")),
+            format!("fn f() {{ {} }}", vec!["f(x)"; n].join(".")),
+            format!(
+                "fn f() {{ if a {{}} {}else {{}} }}",
+                "else if a {} ".repeat(n)
+            ),
+            format!("fn f() {{ {} 1 }}", "if a { ".repeat(n)),
+            format!("fn f(a: {}int) {{}}", "& ".repeat(n)),
+            format!("fn f(a: {}int) {{}}", "&&".repeat(n)),
+            format!("fn f(a: {}int) {{}}", "fn() -> ".repeat(n)),
+            nest("fn f(a: Vec[", "int", "]) {}", n),
+        ]
+    }
+
+    /// Inputs about as deep as the parser accepts, which the passes run on.
+    fn deepest_accepted_inputs() -> Vec<String> {
+        let n = MAX_TREE_DEPTH - 8;
+
+        vec![
+            format!("fn f() {{ {} }}", vec!["1"; n].join(" + ")),
+            format!("fn f() {{ {} }}", vec!["x"; n].join(" * ")),
+            format!("fn f() {{ x{} }}", ".f()".repeat(n / 2)),
+            format!(
+                "fn f() {{ if a {{}} {}else {{}} }}",
+                "else if a {} ".repeat(n)
+            ),
+        ]
+    }
+
+    /// A small deterministic pseudo random number generator, so failures can
+    /// be reproduced.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Random byte strings, with the invalid UTF-8 replaced.
+    fn random_inputs(rng: &mut XorShift, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| {
+                let len = rng.below(64);
+                let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+                String::from_utf8_lossy(&bytes).into_owned()
+            })
+            .collect()
+    }
+
+    /// Random sequences of pieces of Fern, which get much further into the
+    /// parser than random bytes do.
+    fn random_token_soup(rng: &mut XorShift, count: usize) -> Vec<String> {
+        const PIECES: &[&str] = &[
+            "fn", "let", "if", "else", "while", "for", "in", "return", "struct", "true", "x", "f",
+            "int", "1", "0x", "(", ")", "{", "}", "[", "]", ",", ";", ":", "::", ".", "->", "=",
+            "==", "<", "+", "-", "*", "/", "!", "&", "&&", "||", "\"", "'", "//", "/*", "\n", " ",
+        ];
+
+        (0..count)
+            .map(|_| {
+                let len = rng.below(32);
+                (0..len).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+            })
+            .collect()
+    }
+
+    fn assert_compiles_without_panic(text: &str) {
+        if let Err(e) = try_compile(text) {
+            panic!("Compiling {text:?} failed: {e}");
+        }
+    }
+
+    #[test]
+    fn missing_file_doesnt_stop_others() {
+        let valid = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/simple.fern");
+        let missing = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/missing.fern");
+
+        let result = compile_all(&[valid, missing]);
+
+        assert_eq!(result.files.len(), 1);
+        assert_eq!(result.diagnostics.len(), 1);
+
+        let diag = result.diagnostics.iter().next().unwrap();
+        let rendered = diag.render_plain_string(&result.source_map);
+        assert!(rendered.contains("missing.fern"), "{rendered}");
+    }
+
+    #[test]
+    fn nasty_inputs_dont_panic() {
+        for text in NASTY_INPUTS {
+            assert_compiles_without_panic(text);
+        }
+    }
+
+    #[test]
+    fn deep_inputs_dont_panic() {
+        for text in deep_inputs() {
+            assert_compiles_without_panic(&text);
+        }
+    }
+
+    #[test]
+    fn deepest_accepted_inputs_compile() {
+        for text in deepest_accepted_inputs() {
+            let result = try_compile(&text).unwrap();
+            assert!(!result.has_errors(), "{:?}", &text[..40]);
+        }
+    }
+
+    #[test]
+    fn random_inputs_dont_panic() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for text in random_inputs(&mut rng, 2_000) {
+            assert_compiles_without_panic(&text);
+        }
+
+        for text in random_token_soup(&mut rng, 5_000) {
+            assert_compiles_without_panic(&text);
+        }
+    }
+}
@@ -1,18 +1,33 @@
 //! The lexer converts a `Source` into a series of `Token`s.
 
 use crate::{
-    diagnostics::{specifics::lex::mismatched_close_paren, Diagnostic},
+    diagnostics::{specifics::lex::mismatched_close_paren, Diagnostic, Diagnostics},
     source_map::{Source, Span},
+    utils::edit_distance::edit_distance,
     FResult,
 };
+use std::num::IntErrorKind;
 use token::{TokenErrorTy, TokenTree, TokenType};
+use unicode_xid::UnicodeXID;
 
 pub mod token;
 
+/// How deeply delimiters can be nested by default before the lexer gives up
+/// on the contents. See `lex_source_with_max_depth`.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
 pub fn lex_source(source: &Source) -> FResult<Vec<TokenTree>> {
-    let tokens = Lexer::new(source).get_tokens();
+    lex_source_with_max_depth(source, DEFAULT_MAX_NESTING_DEPTH)
+}
 
-    let mut errors = Vec::new();
+/// Lex `source` like `lex_source`, allowing delimiters to be nested at most
+/// `max_depth` levels deep. A delimiter opened beyond that becomes an error
+/// and everything up to its closer is skipped, so later stages never have to
+/// walk arbitrarily deep trees.
+pub fn lex_source_with_max_depth(source: &Source, max_depth: usize) -> FResult<Vec<TokenTree>> {
+    let tokens = Lexer::new(source).with_max_depth(max_depth).get_tokens();
+
+    let mut errors = Diagnostics::new();
     find_errors(&tokens, &source, &mut errors);
 
     if errors.is_empty() {
@@ -22,28 +37,126 @@ pub fn lex_source(source: &Source) -> FResult<Vec<TokenTree>> {
     }
 }
 
+/// Lex `source` like `lex_source`, but rather than converting the errors into
+/// diagnostics, return every error token's kind and span along with the
+/// tokens. The errors are in the order `lex_source` reports them in.
+pub fn lex_source_with_errors(source: &Source) -> (Vec<TokenTree>, Vec<(TokenErrorTy, Span)>) {
+    let tokens = Lexer::new(source).get_tokens();
+
+    let mut errors = Vec::new();
+    collect_errors(&tokens, &mut errors);
+
+    (tokens, errors)
+}
+
+/// Compute the value of the integer literal at `span`.
+///
+/// The literal may have a `0x`, `0o`, or `0b` base prefix and `_` separators.
+/// Any diagnostic quotes the literal exactly as it was written.
+pub fn int_value(span: Span, source: &Source) -> Result<u64, Diagnostic> {
+    use crate::diagnostics::specifics::lex;
+
+    let text = source.text_of_span(span);
+
+    let (radix, digits) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    let digits = digits.replace('_', "");
+
+    u64::from_str_radix(&digits, radix).map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => lex::int_literal_too_large(span, source),
+        _ => lex::malformed_int_literal(span, source),
+    })
+}
+
 struct Lexer<'a> {
     cursor: Cursor<'a>,
+
+    /// How many levels deep delimiters can be nested.
+    max_depth: usize,
 }
 
 impl<'a> Lexer<'a> {
     fn new(source: &'a Source) -> Self {
         let cursor = Cursor::new(source);
-        Self { cursor }
+        Self {
+            cursor,
+            max_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+
+    /// Skip the contents of delimiters nested more than `max_depth` deep,
+    /// rather than the default depth.
+    fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
     fn get_tokens(&mut self) -> Vec<TokenTree> {
         let mut paren_stack: Vec<(TokenType, Span, Vec<TokenTree>)> = Vec::new();
         let mut tokens = Vec::new();
 
+        // While this is non-zero we are skipping the contents of a delimiter
+        // that was nested too deeply, and this is how many levels deep into
+        // it we are.
+        let mut skip_depth = 0;
+
         while let Some(next) = self.cursor.pop() {
+            if skip_depth > 0 {
+                match next {
+                    '(' | '{' | '[' => skip_depth += 1,
+                    ')' | '}' | ']' => skip_depth -= 1,
+                    // Still lex the leaves so delimiters in strings and
+                    // comments aren't counted.
+                    _ => {
+                        self.next_leaf_ty(next);
+                    }
+                }
+                self.cursor.ignore();
+                continue;
+            }
+
             match next {
+                '(' | '{' | '[' if paren_stack.len() >= self.max_depth => {
+                    let token_ty = TokenType::Error(TokenErrorTy::NestingTooDeep);
+                    tokens.push(self.cursor.popped_as_token(token_ty));
+                    skip_depth = 1;
+                }
                 '(' | '{' | '[' => {
                     let ty = TokenType::new_from_paren(next);
                     paren_stack.push((ty, self.cursor.popped_as_span(), tokens));
                     tokens = Vec::new();
                 }
                 ')' | '}' | ']' => {
+                    let close_ty = TokenType::new_from_paren(next);
+
+                    // If this closes a delimiter further down the stack, the
+                    // ones above it were never closed, like the `[` in `([)`.
+                    // Otherwise we pair it with the innermost one even if
+                    // they don't match.
+                    let top_matches = paren_stack.last().is_some_and(|(ty, ..)| *ty == close_ty);
+                    let matching = paren_stack.iter().rposition(|(ty, ..)| *ty == close_ty);
+                    if let (false, Some(matching)) = (top_matches, matching) {
+                        while paren_stack.len() > matching + 1 {
+                            let (open_ty, open_span, mut previous_tokens) =
+                                paren_stack.pop().unwrap();
+
+                            let end = tokens.last().map_or(open_span, TokenTree::span);
+                            let whole_span = Span::union(open_span, end);
+                            tokens.push(TokenTree::new_error(
+                                TokenErrorTy::UnmatchedOpenParen,
+                                open_span,
+                            ));
+
+                            previous_tokens
+                                .push(TokenTree::new_nested(open_ty, whole_span, tokens));
+                            tokens = previous_tokens;
+                        }
+                    }
+
                     let Some((open_ty, open_span, previous_tokens)) = paren_stack.pop() else {
                         // There is no matching parenthesis for this one so
                         // just replace this token with an error.
@@ -52,7 +165,6 @@ impl<'a> Lexer<'a> {
                         continue;
                     };
 
-                    let close_ty = TokenType::new_from_paren(next);
                     let close_span = self.cursor.popped_as_span();
 
                     if open_ty != close_ty {
@@ -79,20 +191,36 @@ impl<'a> Lexer<'a> {
                         // Comments, whitespace, etc. get ignored.
                         continue;
                     };
-                    tokens.push(self.cursor.popped_as_token(ty));
+                    let token = self.cursor.popped_as_token(ty);
+
+                    // A run of illegal characters becomes a single token, so
+                    // it is only reported once.
+                    let illegal = TokenType::Error(TokenErrorTy::IllegalChar);
+                    if let Some(last) = tokens.last_mut() {
+                        if ty == illegal
+                            && last.ty() == illegal
+                            && last.span().end() == token.span().start()
+                        {
+                            *last = TokenTree::new(ty, Span::union(last.span(), token.span()));
+                            continue;
+                        }
+                    }
+                    tokens.push(token);
                 }
             }
         }
 
         // If there is still anything in the stack then that means we had
-        // unmatched opening parenthesis. We will just ignore those opening
-        // parenthesis by replacing them with an error token and concatenating
-        // the whole stack into the current tokens vec.
-        for (_, open_span, mut prev_tokens) in paren_stack.into_iter().rev() {
-            let err_token = TokenTree::new_error(TokenErrorTy::UnmatchedOpenParen, open_span);
-
-            prev_tokens.push(err_token);
-            prev_tokens.extend(tokens);
+        // unmatched opening parenthesis. We close each of them at the end of
+        // the file so the body they contain keeps its structure, and add an
+        // error token at the end of their children.
+        let eof_span = self.cursor.eof_span();
+        for (open_ty, open_span, mut prev_tokens) in paren_stack.into_iter().rev() {
+            let err_token = TokenTree::new_error(TokenErrorTy::UnclosedAtEof, open_span);
+            tokens.push(err_token);
+
+            let whole_span = Span::union(open_span, eof_span);
+            prev_tokens.push(TokenTree::new_nested(open_ty, whole_span, tokens));
             tokens = prev_tokens;
         }
 
@@ -111,21 +239,26 @@ impl<'a> Lexer<'a> {
 
             // Comments
             '/' if cursor.peek_is('/') => {
-                while !cursor.peek_is('\n') {
+                while cursor.peek().is_some_and(|c| c != '\n') {
                     cursor.pop();
                 }
                 cursor.ignore();
                 return None;
             }
-
-            // Literals
-            _ if next.is_ascii_digit() => {
-                while cursor.peek().is_some_and(|c| c.is_ascii_digit()) {
-                    cursor.pop();
+            '/' if cursor.peek_is('*') => {
+                cursor.pop();
+                if !cursor.pop_block_comment() {
+                    return Some(TokenType::Error(TokenErrorTy::UnterminatedBlockComment));
                 }
-                TokenType::IntLit
+                cursor.ignore();
+                return None;
             }
 
+            // Literals
+            _ if next.is_ascii_digit() => self.number_ty(),
+            '"' => self.string_ty(),
+            '\'' => self.char_ty(),
+
             // Identifiers and keywords
             _ if char_can_start_ident(next) => {
                 while cursor.peek().is_some_and(char_can_continue_ident) {
@@ -136,14 +269,35 @@ impl<'a> Lexer<'a> {
             }
 
             // Symbols
+            '+' if cursor.peek_is('=') => {
+                cursor.pop();
+                TokenType::PlusEq
+            }
             '+' => TokenType::Plus,
             '-' if cursor.peek_is('>') => {
                 cursor.pop();
                 TokenType::RArrow
             }
+            '-' if cursor.peek_is('=') => {
+                cursor.pop();
+                TokenType::MinusEq
+            }
             '-' => TokenType::Minus,
+            '*' if cursor.peek_is('=') => {
+                cursor.pop();
+                TokenType::MulEq
+            }
             '*' => TokenType::Mul,
+            '/' if cursor.peek_is('=') => {
+                cursor.pop();
+                TokenType::DivEq
+            }
             '/' => TokenType::Div,
+            '%' if cursor.peek_is('=') => {
+                cursor.pop();
+                TokenType::PercentEq
+            }
+            '%' => TokenType::Percent,
             '!' if cursor.peek_is('=') => {
                 cursor.pop();
                 TokenType::NotEq
@@ -153,28 +307,45 @@ impl<'a> Lexer<'a> {
                 cursor.pop();
                 TokenType::OrOr
             }
+            '|' => TokenType::BitOr,
             '&' if cursor.peek_is('&') => {
                 cursor.pop();
                 TokenType::AndAnd
             }
+            '&' => TokenType::BitAnd,
+            '^' => TokenType::BitXor,
+            '~' => TokenType::Tilde,
             '=' if cursor.peek_is('=') => {
                 cursor.pop();
                 TokenType::EqEq
             }
             '=' => TokenType::Eq,
+            '<' if cursor.peek_is('<') => {
+                cursor.pop();
+                TokenType::Shl
+            }
             '<' if cursor.peek_is('=') => {
                 cursor.pop();
                 TokenType::Lte
             }
             '<' => TokenType::Lt,
+            '>' if cursor.peek_is('>') => {
+                cursor.pop();
+                TokenType::Shr
+            }
             '>' if cursor.peek_is('=') => {
                 cursor.pop();
                 TokenType::Gte
             }
             '>' => TokenType::Gt,
             ';' => TokenType::Semicolon,
+            ':' if cursor.peek_is(':') => {
+                cursor.pop();
+                TokenType::DoubleColon
+            }
             ':' => TokenType::Colon,
             ',' => TokenType::Comma,
+            '.' => TokenType::Dot,
 
             // Parenthesis
             '(' | ')' | '{' | '}' | '[' | ']' => {
@@ -189,25 +360,215 @@ impl<'a> Lexer<'a> {
 
         Some(ty)
     }
+
+    /// Lex the rest of a number literal whose first digit was just popped,
+    /// returning whether it is an integer or a float.
+    fn number_ty(&mut self) -> TokenType {
+        let radix = match (self.cursor.popped_text(), self.cursor.peek()) {
+            ("0", Some('x')) => 16,
+            ("0", Some('o')) => 8,
+            ("0", Some('b')) => 2,
+            _ => 10,
+        };
+
+        let ty = if radix == 10 {
+            self.decimal_number_ty()
+        } else {
+            self.cursor.pop();
+            self.radix_int_ty(radix)
+        };
+
+        // Separators have to come between digits, so `1__0` and `1_` are
+        // malformed.
+        let text = self.cursor.popped_text();
+        let misplaced_separator = text.char_indices().any(|(i, c)| {
+            c == '_' && !text[i + 1..].starts_with(|c: char| c.is_ascii_alphanumeric())
+        });
+
+        match ty {
+            TokenType::Error(_) => ty,
+            _ if misplaced_separator => TokenType::Error(TokenErrorTy::MalformedNumber),
+            _ => ty,
+        }
+    }
+
+    /// Lex the rest of a base 10 number literal, which may be a float.
+    fn decimal_number_ty(&mut self) -> TokenType {
+        let cursor = &mut self.cursor;
+        let is_digit_at =
+            |cursor: &Cursor, n: usize| cursor.peek_nth(n).is_some_and(|c| c.is_ascii_digit());
+
+        let mut ty = TokenType::IntLit;
+        cursor.pop_digits();
+
+        // A '.' is only part of the number if a digit follows it, so that
+        // `1.foo` and `1.method()` stay field accesses on an integer.
+        if cursor.peek_is('.') && is_digit_at(cursor, 1) {
+            cursor.pop();
+            cursor.pop_digits();
+            ty = TokenType::FloatLit;
+        }
+
+        // Likewise an exponent needs digits, optionally after a sign.
+        if matches!(cursor.peek(), Some('e' | 'E')) {
+            let sign_len = usize::from(matches!(cursor.peek_nth(1), Some('+' | '-')));
+            if is_digit_at(cursor, 1 + sign_len) {
+                for _ in 0..1 + sign_len {
+                    cursor.pop();
+                }
+                cursor.pop_digits();
+                ty = TokenType::FloatLit;
+            }
+        }
+
+        ty
+    }
+
+    /// Lex the digits of an integer literal after its base prefix.
+    ///
+    /// We take letters as well as digits, so that a literal like `0xZ` or
+    /// `0b12` is reported as having a bad digit rather than being split up.
+    fn radix_int_ty(&mut self, radix: u32) -> TokenType {
+        let cursor = &mut self.cursor;
+        let mut invalid_digit = None;
+
+        while let Some(c) = cursor
+            .peek()
+            .filter(|&c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            let start = cursor.byte_offset;
+            cursor.pop();
+
+            if !c.is_digit(radix) && c != '_' && invalid_digit.is_none() {
+                invalid_digit = Some(cursor.source.span(start, cursor.byte_offset));
+            }
+        }
+
+        match invalid_digit {
+            Some(digit_span) => TokenType::Error(TokenErrorTy::InvalidDigitForBase { digit_span }),
+            None => TokenType::IntLit,
+        }
+    }
+
+    /// Lex the rest of a string literal whose opening quote was just popped.
+    /// A string without a closing quote extends to the end of the file.
+    fn string_ty(&mut self) -> TokenType {
+        // We report the first invalid escape, but keep going to the closing
+        // quote so the rest of the string isn't lexed as code.
+        let mut invalid_escape = None;
+
+        while let Some(c) = self.cursor.pop() {
+            match c {
+                '"' => {
+                    return match invalid_escape {
+                        Some(escape_span) => {
+                            TokenType::Error(TokenErrorTy::InvalidEscape { escape_span })
+                        }
+                        None => TokenType::StrLit,
+                    };
+                }
+                '\\' => {
+                    if let Err(escape_span) = self.pop_escape() {
+                        invalid_escape = invalid_escape.or(Some(escape_span));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        TokenType::Error(TokenErrorTy::UnterminatedString)
+    }
+
+    /// Lex the rest of a character literal whose opening quote was just
+    /// popped. A character literal without a closing quote extends to the end
+    /// of its line.
+    fn char_ty(&mut self) -> TokenType {
+        let mut char_count = 0;
+        let mut invalid_escape = None;
+
+        loop {
+            match self.cursor.peek() {
+                None | Some('\n') => {
+                    return TokenType::Error(TokenErrorTy::UnterminatedCharLit);
+                }
+                Some('\'') => {
+                    self.cursor.pop();
+                    break;
+                }
+                Some(_) => {
+                    if self.cursor.pop() == Some('\\') {
+                        if let Err(escape_span) = self.pop_escape() {
+                            invalid_escape = invalid_escape.or(Some(escape_span));
+                        }
+                    }
+                    char_count += 1;
+                }
+            }
+        }
+
+        match (char_count, invalid_escape) {
+            (0, _) => TokenType::Error(TokenErrorTy::EmptyCharLit),
+            (1, Some(escape_span)) => TokenType::Error(TokenErrorTy::InvalidEscape { escape_span }),
+            (1, None) => TokenType::CharLit,
+            _ => TokenType::Error(TokenErrorTy::CharLitTooLong),
+        }
+    }
+
+    /// Pop the character after a `\` that was just popped, so that an escaped
+    /// quote doesn't end a literal.
+    ///
+    /// Returns the span of the escape sequence if it isn't one we recognize.
+    fn pop_escape(&mut self) -> Result<(), Span> {
+        let start = self.cursor.byte_offset - '\\'.len_utf8();
+
+        match self.cursor.pop() {
+            // A `\` at the end of the file is reported as an unterminated
+            // literal instead.
+            None | Some('"' | '\'' | '\\' | 'n' | 't' | 'r' | '0') => Ok(()),
+            Some(_) => Err(self.cursor.source.span(start, self.cursor.byte_offset)),
+        }
+    }
 }
 
 fn char_can_continue_ident(c: char) -> bool {
-    char_can_start_ident(c) || c.is_ascii_digit()
+    c.is_xid_continue()
 }
 
 fn char_can_start_ident(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
+    c.is_xid_start() || c == '_'
 }
 
+/// Every keyword and the token type it lexes to.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("fn", TokenType::Fn),
+    ("let", TokenType::Let),
+    ("if", TokenType::If),
+    ("else", TokenType::Else),
+    ("while", TokenType::While),
+    ("for", TokenType::For),
+    ("in", TokenType::In),
+    ("return", TokenType::Return),
+    ("struct", TokenType::Struct),
+    ("enum", TokenType::Enum),
+    ("impl", TokenType::Impl),
+    ("true", TokenType::BoolLit),
+    ("false", TokenType::BoolLit),
+];
+
 fn ident_token_ty(ident: &str) -> TokenType {
-    match ident {
-        "fn" => TokenType::Fn,
-        "let" => TokenType::Let,
-        "if" => TokenType::If,
-        "while" => TokenType::While,
-        "for" => TokenType::For,
-        _ => TokenType::Ident,
-    }
+    KEYWORDS
+        .iter()
+        .find(|(kw, _)| *kw == ident)
+        .map_or(TokenType::Ident, |&(_, ty)| ty)
+}
+
+/// The keyword out of `keywords` the identifier `ident` is probably a typo of,
+/// if there is one.
+pub fn similar_keyword(ident: &str, keywords: &[&'static str]) -> Option<&'static str> {
+    keywords
+        .iter()
+        .copied()
+        .find(|kw| edit_distance(kw, ident) == 1)
 }
 
 struct Cursor<'a> {
@@ -239,6 +600,11 @@ impl<'a> Cursor<'a> {
         self.remaining_text().chars().next()
     }
 
+    /// The character `n` characters after the next one.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.remaining_text().chars().nth(n)
+    }
+
     fn peek_is(&self, c: char) -> bool {
         self.peek() == Some(c)
     }
@@ -252,6 +618,39 @@ impl<'a> Cursor<'a> {
         Some(c)
     }
 
+    /// Pop every ASCII digit and `_` separator up to the next other character.
+    fn pop_digits(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.pop();
+        }
+    }
+
+    /// Pop the rest of a block comment whose `/*` was just popped, including
+    /// any block comments nested inside it.
+    ///
+    /// Returns false if the end of the file is reached before the comment is
+    /// closed.
+    fn pop_block_comment(&mut self) -> bool {
+        let mut depth = 1;
+
+        while depth > 0 {
+            match self.pop() {
+                Some('/') if self.peek_is('*') => {
+                    self.pop();
+                    depth += 1;
+                }
+                Some('*') if self.peek_is('/') => {
+                    self.pop();
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => return false,
+            }
+        }
+
+        true
+    }
+
     fn popped_text(&self) -> &str {
         &self.text()[self.span_offset..self.span_offset + self.span_len]
     }
@@ -269,31 +668,453 @@ impl<'a> Cursor<'a> {
         TokenTree::new(ty, self.popped_as_span())
     }
 
+    /// A zero width span at the very end of the source.
+    fn eof_span(&self) -> Span {
+        self.source.span_with_len(self.text().len(), 0)
+    }
+
     fn ignore(&mut self) {
         self.span_offset = self.byte_offset;
         self.span_len = 0;
     }
 }
 
-fn find_errors(tokens: &[TokenTree], source: &Source, errors: &mut Vec<Diagnostic>) {
-    for token in tokens {
-        find_errors(token.children(), source, errors);
+/// Collect the kind and span of every error token in `tokens`. The errors
+/// inside a nested token come before any error with the token itself.
+///
+/// This uses an explicit stack rather than recursion so deeply nested trees
+/// can't overflow the call stack.
+fn collect_errors(tokens: &[TokenTree], errors: &mut Vec<(TokenErrorTy, Span)>) {
+    // Each token is visited twice: first to queue its children, then once
+    // they are done to check the token itself.
+    let mut stack: Vec<(&TokenTree, bool)> = tokens.iter().rev().map(|t| (t, false)).collect();
 
-        let TokenType::Error(ty) = token.ty() else {
+    while let Some((token, children_done)) = stack.pop() {
+        if !children_done {
+            stack.push((token, true));
+            stack.extend(token.children().iter().rev().map(|t| (t, false)));
             continue;
-        };
+        }
 
+        if let TokenType::Error(ty) = token.ty() {
+            errors.push((ty, token.span()));
+        }
+    }
+}
+
+/// Report every error token in `tokens` and their children, in the order
+/// `collect_errors` finds them.
+fn find_errors(tokens: &[TokenTree], source: &Source, errors: &mut Diagnostics) {
+    let mut error_tokens = Vec::new();
+    collect_errors(tokens, &mut error_tokens);
+
+    for (ty, span) in error_tokens {
         use crate::diagnostics::specifics::lex;
         use TokenErrorTy as TET;
 
         let error = match ty {
-            TET::IllegalChar => lex::illegal_char(token.span(), source),
-            TET::UnmatchedOpenParen => lex::unmatched_open_paren(token.span(), source),
-            TET::UnmatchedCloseParen => lex::unmatched_close_paren(token.span(), source),
-            TET::MismatchedParenTy { open_span } => {
-                mismatched_close_paren(open_span, token.span(), source)
+            TET::IllegalChar => lex::illegal_char(span, source),
+            TET::NestingTooDeep => lex::nesting_too_deep(span, source),
+            TET::UnmatchedOpenParen => lex::unmatched_open_paren(span, source),
+            TET::UnclosedAtEof => lex::unclosed_at_eof(span, source),
+            TET::UnmatchedCloseParen => lex::unmatched_close_paren(span, source),
+            TET::UnterminatedString => lex::unterminated_string(span, source),
+            TET::InvalidEscape { escape_span } => lex::invalid_escape(escape_span, source),
+            TET::InvalidDigitForBase { digit_span } => {
+                lex::invalid_digit_for_base(span, digit_span, source)
             }
+            TET::MalformedNumber => lex::malformed_number(span, source),
+            TET::UnterminatedBlockComment => lex::unterminated_block_comment(span, source),
+            TET::UnterminatedCharLit => lex::unterminated_char_lit(span, source),
+            TET::EmptyCharLit => lex::empty_char_lit(span, source),
+            TET::CharLitTooLong => lex::char_lit_too_long(span, source),
+            TET::MismatchedParenTy { open_span } => mismatched_close_paren(open_span, span, source),
         };
         errors.push(error);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+    use std::ops::Range;
+
+    /// Lex `text` as a source of its own, failing the test if it has errors.
+    fn lex(text: &str) -> Vec<TokenTree> {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+
+        match lex_source(source_map.get_source(id)) {
+            Ok(tokens) => tokens,
+            Err(_) => panic!("`{text}` has lexer errors"),
+        }
+    }
+
+    /// The types of the top level tokens in `text`, which must not have
+    /// errors.
+    fn types(text: &str) -> Vec<TokenType> {
+        lex(text).iter().map(TokenTree::ty).collect()
+    }
+
+    /// Every error in `text` along with the bytes it covers.
+    fn errors(text: &str) -> Vec<(TokenErrorTy, Range<usize>)> {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+
+        let (_, errors) = lex_source_with_errors(source_map.get_source(id));
+        errors
+            .into_iter()
+            .map(|(error, span)| (error, span.byte_range()))
+            .collect()
+    }
+
+    #[test]
+    fn line_comment_at_end_of_file() {
+        assert!(lex("// trailing").is_empty());
+    }
+
+    #[test]
+    fn too_large_int_is_quoted_as_written() {
+        let text = "0xFFFF_FFFF_FFFF_FFFF_FF";
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+        let source = source_map.get_source(id);
+
+        let diag = int_value(source.span(0, text.len()), source).unwrap_err();
+        let rendered = diag.render_plain_string(&source_map);
+
+        assert!(
+            rendered.contains(&format!("`{text}` is too large")),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn float_literals() {
+        use TokenType as T;
+
+        for text in ["0.0", "10.5", "1e9", "2.5e-3", "1E+2"] {
+            assert_eq!(types(text), [T::FloatLit], "lexing `{text}`");
+        }
+
+        // A `.` that isn't followed by a digit is a field access or method
+        // call on the integer.
+        assert_eq!(
+            types("1.method()"),
+            [T::IntLit, T::Dot, T::Ident, T::Parens]
+        );
+        assert_eq!(types("1."), [T::IntLit, T::Dot]);
+        assert_eq!(types("1.e3"), [T::IntLit, T::Dot, T::Ident]);
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(types(r#""hello""#), [TokenType::StrLit]);
+        assert_eq!(types(r#""""#), [TokenType::StrLit]);
+
+        // The error covers the rest of the text.
+        assert_eq!(
+            errors(r#"a "oops"#),
+            [(TokenErrorTy::UnterminatedString, 2..7)]
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(types(r#""a\"b""#), [TokenType::StrLit]);
+        assert_eq!(types(r#""\\ \n \t \0""#), [TokenType::StrLit]);
+        assert_eq!(types(r#""\\" x"#), [TokenType::StrLit, TokenType::Ident]);
+
+        let errors = errors(r#""a\qb""#);
+        let [(TokenErrorTy::InvalidEscape { escape_span }, _)] = errors[..] else {
+            panic!("expected an invalid escape, got {errors:?}");
+        };
+        assert_eq!(escape_span.byte_range(), 2..4);
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(types("'a'"), [TokenType::CharLit]);
+        assert_eq!(types(r"'\n'"), [TokenType::CharLit]);
+        assert_eq!(types("c 'c'"), [TokenType::Ident, TokenType::CharLit]);
+        assert_eq!(errors("''"), [(TokenErrorTy::EmptyCharLit, 0..2)]);
+        assert_eq!(errors("'ab'"), [(TokenErrorTy::CharLitTooLong, 0..4)]);
+    }
+
+    #[test]
+    fn based_int_literals() {
+        for text in ["0", "0xFF", "0o17", "0b1010"] {
+            assert_eq!(types(text), [TokenType::IntLit], "lexing `{text}`");
+        }
+
+        for (text, digit) in [("0b12", 3..4), ("0xZ", 2..3), ("0o78", 3..4)] {
+            let errors = errors(text);
+            let [(TokenErrorTy::InvalidDigitForBase { digit_span }, _)] = errors[..] else {
+                panic!("expected an invalid digit in `{text}`, got {errors:?}");
+            };
+            assert_eq!(digit_span.byte_range(), digit, "lexing `{text}`");
+        }
+    }
+
+    #[test]
+    fn underscores_in_numbers() {
+        for text in ["1_000", "1_0_0", "0xFF_FF"] {
+            assert_eq!(types(text), [TokenType::IntLit], "lexing `{text}`");
+        }
+
+        for text in ["1__0", "1_", "1_000_"] {
+            let expected = (TokenErrorTy::MalformedNumber, 0..text.len());
+            assert_eq!(errors(text), [expected], "lexing `{text}`");
+        }
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_eq!(types("a /* b */ c"), [TokenType::Ident, TokenType::Ident]);
+        assert_eq!(
+            types("a /* b /* c */ d */ e"),
+            [TokenType::Ident, TokenType::Ident]
+        );
+
+        // The comment takes up the rest of the text, but the diagnostic only
+        // points at the `/*` that was never closed.
+        let text = "a /* b /* c */ d";
+        let expected = [(TokenErrorTy::UnterminatedBlockComment, 2..16)];
+        assert_eq!(errors(text), expected);
+
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+        let Err(diags) = lex_source(source_map.get_source(id)) else {
+            panic!("the unterminated comment wasn't reported");
+        };
+        let rendered = diags
+            .iter()
+            .next()
+            .unwrap()
+            .render_plain_string(&source_map);
+        assert!(
+            rendered.contains("  ^^ this comment is never closed"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn dot() {
+        use TokenType as T;
+
+        assert_eq!(types("a.b"), [T::Ident, T::Dot, T::Ident]);
+        assert_eq!(types("a.1.5"), [T::Ident, T::Dot, T::FloatLit]);
+    }
+
+    #[test]
+    fn double_colon() {
+        use TokenType as T;
+
+        assert_eq!(types("a::b"), [T::Ident, T::DoubleColon, T::Ident]);
+        assert_eq!(types("a: b"), [T::Ident, T::Colon, T::Ident]);
+        assert_eq!(types(":::"), [T::DoubleColon, T::Colon]);
+    }
+
+    #[test]
+    fn arithmetic_and_bitwise_operators() {
+        use TokenType as T;
+
+        assert_eq!(types("a % b"), [T::Ident, T::Percent, T::Ident]);
+        assert_eq!(types("a << 2"), [T::Ident, T::Shl, T::IntLit]);
+        assert_eq!(types("a >> 2"), [T::Ident, T::Shr, T::IntLit]);
+        assert_eq!(types("x & y"), [T::Ident, T::BitAnd, T::Ident]);
+        assert_eq!(
+            types("x | y ^ ~z"),
+            [T::Ident, T::BitOr, T::Ident, T::BitXor, T::Tilde, T::Ident]
+        );
+
+        // The operators that start with the same characters still lex as
+        // before.
+        assert_eq!(types("x && y"), [T::Ident, T::AndAnd, T::Ident]);
+        assert_eq!(types("x || y"), [T::Ident, T::OrOr, T::Ident]);
+        assert_eq!(types("<= >= <"), [T::Lte, T::Gte, T::Lt]);
+    }
+
+    #[test]
+    fn compound_assignment() {
+        use TokenType as T;
+
+        let ops = [T::PlusEq, T::MinusEq, T::MulEq, T::DivEq, T::PercentEq];
+        assert_eq!(types("+= -= *= /= %="), ops);
+        assert_eq!(types("a = b"), [T::Ident, T::Eq, T::Ident]);
+        assert_eq!(types("-> - ="), [T::RArrow, T::Minus, T::Eq]);
+    }
+
+    #[test]
+    fn else_and_return_keywords() {
+        use TokenType as T;
+
+        assert_eq!(types("else return"), [T::Else, T::Return]);
+        assert_eq!(types("elsex returns"), [T::Ident, T::Ident]);
+    }
+
+    #[test]
+    fn bool_literals() {
+        use TokenType as T;
+
+        assert_eq!(types("true false"), [T::BoolLit, T::BoolLit]);
+        assert_eq!(types("trueish"), [T::Ident]);
+    }
+
+    #[test]
+    fn type_keywords() {
+        use TokenType as T;
+
+        assert_eq!(types("struct enum impl"), [T::Struct, T::Enum, T::Impl]);
+        assert_eq!(types("structure enums implementation"), [T::Ident; 3]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        use TokenType as T;
+
+        assert_eq!(types("café"), [T::Ident]);
+        assert_eq!(types("πr * _β2"), [T::Ident, T::Mul, T::Ident]);
+        // Emoji aren't letters, so they still can't be in identifiers.
+        assert_eq!(errors("a🦀"), [(TokenErrorTy::IllegalChar, 1..5)]);
+    }
+
+    #[test]
+    fn lexes_token_trees() {
+        use TokenType as T;
+
+        let tokens = lex("fn f(a) { x }");
+
+        let top_level: Vec<_> = tokens.iter().map(TokenTree::ty).collect();
+        assert_eq!(top_level, [T::Fn, T::Ident, T::Parens, T::CurlyBrackets]);
+        assert_eq!(tokens[2].children()[0].ty(), T::Ident);
+        assert_eq!(tokens[3].children()[0].span().byte_range(), 10..11);
+    }
+
+    #[test]
+    fn illegal_char_through_errors_api() {
+        assert_eq!(errors("a @ b"), [(TokenErrorTy::IllegalChar, 2..3)]);
+    }
+
+    #[test]
+    fn run_of_illegal_chars_is_one_error() {
+        assert_eq!(errors("a @@@ b"), [(TokenErrorTy::IllegalChar, 2..5)]);
+
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("a @@@ b"));
+        let Err(diags) = lex_source(source_map.get_source(id)) else {
+            panic!("the illegal characters weren't reported");
+        };
+        assert_eq!(diags.iter().count(), 1);
+
+        let rendered = diags
+            .iter()
+            .next()
+            .unwrap()
+            .render_plain_string(&source_map);
+        assert!(rendered.contains("Illegal characters `@@@`."), "{rendered}");
+        assert!(rendered.contains("  ^^^"), "{rendered}");
+    }
+
+    #[test]
+    fn mismatched_closers_recover() {
+        let mut source_map = SourceMap::new();
+        let crossed = source_map.add_source(String::from("a.fern"), String::from("([)]"));
+        let wrong = source_map.add_source(String::from("b.fern"), String::from("(a]"));
+        let debug = |id| {
+            let source = source_map.get_source(id);
+            let (tokens, errors) = lex_source_with_errors(source);
+            let trees: Vec<String> = tokens.iter().map(|t| t.debug_string(source)).collect();
+            (trees, errors.len())
+        };
+
+        // The `)` closes the `(` it matches, rather than being paired with
+        // the `[` and leaving the `(` open.
+        let (trees, error_count) = debug(crossed);
+        assert_eq!(
+            trees,
+            [
+                "Parens[Brackets[Error(UnmatchedOpenParen)([)]]",
+                "Error(UnmatchedCloseParen)(])"
+            ]
+        );
+        assert_eq!(error_count, 2);
+
+        // Nothing else is open, so the `]` still closes the `(`.
+        let (trees, error_count) = debug(wrong);
+        assert_eq!(trees.len(), 1);
+        assert!(trees[0].starts_with("Parens[Ident(a), Error(MismatchedParenTy"));
+        assert_eq!(error_count, 1);
+
+        // A delimiter still open at the end of the file is a different error,
+        // since no closer was ever found for it.
+        let expected = [
+            (TokenErrorTy::UnmatchedOpenParen, 1..2),
+            (TokenErrorTy::UnclosedAtEof, 3..4),
+        ];
+        assert_eq!(errors("([)("), expected);
+    }
+
+    #[test]
+    fn nesting_beyond_the_limit() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("((([a]) b)) c"));
+        let Err(diags) = lex_source_with_max_depth(source_map.get_source(id), 3) else {
+            panic!("the nesting wasn't reported");
+        };
+        assert_eq!(diags.iter().count(), 1);
+
+        let rendered = diags
+            .iter()
+            .next()
+            .unwrap()
+            .render_plain_string(&source_map);
+        assert!(
+            rendered.contains("This `[` is nested too deeply."),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("   ^ its contents are skipped"),
+            "{rendered}"
+        );
+
+        // Far deeper input than the limit is still lexed without recursing
+        // into it.
+        let depth = 100_000;
+        let text = format!("{}{}", "(".repeat(depth), ")".repeat(depth));
+        let id = source_map.add_source(String::from("deep.fern"), text);
+        let Err(diags) = lex_source(source_map.get_source(id)) else {
+            panic!("the nesting wasn't reported");
+        };
+        assert_eq!(diags.iter().count(), 1);
+    }
+
+    #[test]
+    fn errors_in_deep_nesting() {
+        use TokenErrorTy as TET;
+
+        let expected = [
+            (TET::IllegalChar, 1..2),
+            (TET::IllegalChar, 3..4),
+            (TET::UnmatchedOpenParen, 2..3),
+            (TET::IllegalChar, 6..7),
+            (TET::UnmatchedCloseParen, 7..8),
+        ];
+        // The `$` inside the `[` is found before the `[` itself.
+        assert_eq!(errors("(@[$) #]"), expected);
+
+        // Finding the errors doesn't recurse, so nesting far deeper than the
+        // stack allows is still fine without a limit.
+        let depth = 100_000;
+        let text = format!("{}@{}", "(".repeat(depth), ")".repeat(depth));
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text);
+        let source = source_map.get_source(id);
+
+        let Err(diags) = lex_source_with_max_depth(source, usize::MAX) else {
+            panic!("the illegal character wasn't reported");
+        };
+        assert_eq!(diags.iter().count(), 1);
+    }
+}
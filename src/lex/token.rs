@@ -1,4 +1,59 @@
-use crate::source_map::Span;
+use crate::{
+    source_map::{Source, Span},
+    utils::{
+        consistency_assert,
+        tree_writer::{print_tree_iterative, TreeField, TreeShape},
+    },
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::{self, discriminant, Discriminant},
+    ops::Range,
+};
+
+/// Print `tokens` as a tree, with each token's type and text and the children
+/// of nested tokens indented below them.
+pub fn pretty_print_tokens(tokens: &[TokenTree], source: &Source, wr: &mut dyn fmt::Write) {
+    let text = print_tree_iterative(TokenNode::Root(tokens), &mut |node| match node {
+        TokenNode::Root(tokens) => TreeShape::Node {
+            name: "Tokens",
+            fields: vec![(
+                "tokens",
+                TreeField::List(tokens.iter().map(TokenNode::Token).collect()),
+            )],
+        },
+        TokenNode::Token(token) => {
+            let name = match token.ty() {
+                TokenType::Parens => "Parens",
+                TokenType::Brackets => "Brackets",
+                TokenType::CurlyBrackets => "CurlyBrackets",
+                ty => {
+                    let text = source.text_of_span(token.span());
+                    return TreeShape::Leaf(format!("{ty:?} `{text}`"));
+                }
+            };
+            let children = token.children().iter().map(TokenNode::Token).collect();
+
+            TreeShape::Node {
+                name,
+                fields: vec![("children", TreeField::List(children))],
+            }
+        }
+    });
+    write!(wr, "{text}");
+}
+
+/// A node printed by `pretty_print_tokens`.
+#[derive(Clone, Copy)]
+enum TokenNode<'a> {
+    /// The list of all the token trees of a source.
+    Root(&'a [TokenTree]),
+
+    /// A single token tree.
+    Token(&'a TokenTree),
+}
 
 pub struct TokenTree {
     ty: TokenType,
@@ -20,7 +75,7 @@ impl TokenTree {
     }
 
     pub fn new_nested(ty: TokenType, span: Span, children: Vec<TokenTree>) -> Self {
-        assert!(ty.is_nested(), "Only nested tokens can have children");
+        consistency_assert!(ty.is_nested(), "Only nested tokens can have children");
 
         Self { ty, span, children }
     }
@@ -36,6 +91,212 @@ impl TokenTree {
     pub fn children(&self) -> &[TokenTree] {
         &self.children
     }
+
+    /// A compact, single line description of this tree for tests and
+    /// debugging, like `Parens[Ident(a), Comma(,), IntLit(1)]`. Leaves show
+    /// their text and nested tokens show their children.
+    pub fn debug_string(&self, source: &Source) -> String {
+        if !self.ty.is_nested() {
+            return format!("{:?}({})", self.ty, source.text_of_span(self.span));
+        }
+
+        let children: Vec<String> = self
+            .children
+            .iter()
+            .map(|child| child.debug_string(source))
+            .collect();
+        format!("{:?}[{}]", self.ty, children.join(", "))
+    }
+
+    /// The tokens in this tree that aren't nested, in source order. This is
+    /// the token itself if it isn't nested, and otherwise the leaves of its
+    /// children. The delimiters themselves aren't included.
+    pub fn leaves(&self) -> Leaves<'_> {
+        Leaves::new(std::slice::from_ref(self))
+    }
+
+    /// A key that compares and hashes this tree by its contents, ignoring
+    /// where in `source` it is. Two trees with equal keys lex the same text
+    /// into the same structure, so anything computed from one can be reused
+    /// for the other.
+    pub fn content_key<'a>(&'a self, source: &'a Source) -> TokenTreeKey<'a> {
+        TokenTreeKey { tree: self, source }
+    }
+
+    /// A hash of the contents of this tree, consistent with `content_key`.
+    pub fn content_hash(&self, source: &Source) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.content_key(source).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The byte range of `span` relative to the byte `base`. This is signed,
+    /// since an error can refer to a span before the token it is on, like the
+    /// opening parenthesis a closing one doesn't match.
+    fn relative_range(span: Span, base: usize) -> Range<isize> {
+        let range = span.byte_range();
+        let relative = |byte: usize| byte as isize - base as isize;
+        relative(range.start)..relative(range.end)
+    }
+
+    /// The parts of our type that don't depend on absolute positions.
+    fn relative_ty(&self, base: usize) -> RelativeTy {
+        let TokenType::Error(error_ty) = self.ty else {
+            return (discriminant(&self.ty), None);
+        };
+
+        let error_range = match error_ty {
+            TokenErrorTy::MismatchedParenTy { open_span } => {
+                Some(Self::relative_range(open_span, base))
+            }
+            TokenErrorTy::InvalidEscape { escape_span } => {
+                Some(Self::relative_range(escape_span, base))
+            }
+            TokenErrorTy::InvalidDigitForBase { digit_span } => {
+                Some(Self::relative_range(digit_span, base))
+            }
+            _ => None,
+        };
+
+        (
+            discriminant(&self.ty),
+            Some((discriminant(&error_ty), error_range)),
+        )
+    }
+
+    /// The text of this token, or nothing for nested tokens whose contents
+    /// are compared through their children.
+    fn leaf_text<'a>(&self, source: &'a Source) -> &'a str {
+        if self.ty.is_nested() {
+            ""
+        } else {
+            source.text_of_span(self.span)
+        }
+    }
+
+    /// Compare the contents of `self` and `other` where positions are taken
+    /// relative to the bytes `base` and `other_base` respectively.
+    fn content_eq(
+        &self,
+        base: usize,
+        other: &TokenTree,
+        other_base: usize,
+        sources: (&Source, &Source),
+    ) -> bool {
+        let (source, other_source) = sources;
+
+        let node_eq = self.relative_ty(base) == other.relative_ty(other_base)
+            && Self::relative_range(self.span, base)
+                == Self::relative_range(other.span, other_base)
+            && self.leaf_text(source) == other.leaf_text(other_source)
+            && self.children.len() == other.children.len();
+
+        node_eq
+            && (self.children.iter().zip(&other.children)).all(|(child, other_child)| {
+                child.content_eq(base, other_child, other_base, sources)
+            })
+    }
+
+    /// Hash the contents of this tree, consistently with `content_eq`.
+    fn content_hash_into(&self, source: &Source, base: usize, state: &mut impl Hasher) {
+        self.relative_ty(base).hash(state);
+        Self::relative_range(self.span, base).hash(state);
+        self.leaf_text(source).hash(state);
+        self.children.len().hash(state);
+
+        for child in &self.children {
+            child.content_hash_into(source, base, state);
+        }
+    }
+}
+
+/// Drops the children one at a time rather than recursively, since a tree
+/// lexed without a nesting limit can be deep enough to overflow the stack.
+impl Drop for TokenTree {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+/// The position independent parts of a `TokenType`: its variant and, for
+/// errors, the error variant and any span relative to the tree it is in.
+type RelativeTy = (
+    Discriminant<TokenType>,
+    Option<(Discriminant<TokenErrorTy>, Option<Range<isize>>)>,
+);
+
+/// An iterator over the non-nested tokens in a list of token trees, in source
+/// order. See `TokenTree::leaves`.
+pub struct Leaves<'a> {
+    /// The trees left to visit at each level of nesting we are in.
+    stack: Vec<std::slice::Iter<'a, TokenTree>>,
+}
+
+impl<'a> Leaves<'a> {
+    /// Iterate over the leaves of all of `tokens`, such as those returned by
+    /// `lex_source`.
+    pub fn new(tokens: &'a [TokenTree]) -> Self {
+        Self {
+            stack: vec![tokens.iter()],
+        }
+    }
+}
+
+impl<'a> Iterator for Leaves<'a> {
+    type Item = &'a TokenTree;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(token) = self.stack.last_mut()?.next() else {
+                self.stack.pop();
+                continue;
+            };
+
+            if token.ty().is_nested() {
+                self.stack.push(token.children().iter());
+            } else {
+                return Some(token);
+            }
+        }
+    }
+}
+
+/// Compares and hashes a `TokenTree` by its contents rather than its position.
+/// See `TokenTree::content_key`.
+#[derive(Clone, Copy)]
+pub struct TokenTreeKey<'a> {
+    /// The tree that is compared.
+    tree: &'a TokenTree,
+
+    /// The source the tree is from, which its contents are looked up in.
+    source: &'a Source,
+}
+
+impl TokenTreeKey<'_> {
+    /// The byte all positions in the tree are taken relative to.
+    fn base(&self) -> usize {
+        self.tree.span.start().byte()
+    }
+}
+
+impl PartialEq for TokenTreeKey<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let sources = (self.source, other.source);
+        self.tree
+            .content_eq(self.base(), other.tree, other.base(), sources)
+    }
+}
+
+impl Eq for TokenTreeKey<'_> {}
+
+impl Hash for TokenTreeKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tree.content_hash_into(self.source, self.base(), state);
+    }
 }
 
 /// The lexical category of a `Token`.
@@ -44,47 +305,126 @@ impl TokenTree {
 /// discarded immediately.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
+    /// An identifier, like the name of a variable or function.
     Ident,
 
     // Literals
+    /// An integer literal.
     IntLit,
+    /// A float literal.
+    FloatLit,
+    /// A string literal.
+    StrLit,
+    /// A character literal.
+    CharLit,
+    /// A boolean literal.
+    BoolLit,
 
     // Keywords
+    /// The keyword `fn`.
     Fn,
+    /// The keyword `let`.
     Let,
+    /// The keyword `if`.
     If,
+    /// The keyword `else`.
+    Else,
+    /// The keyword `while`.
     While,
+    /// The keyword `for`.
     For,
+    /// The keyword `in`.
+    In,
+    /// The keyword `return`.
+    Return,
+    /// The keyword `struct`.
+    Struct,
+    /// The keyword `enum`.
+    Enum,
+    /// The keyword `impl`.
+    Impl,
 
     // Nested
+    /// Parentheses, with the tokens between them as children.
     Parens,
+    /// Square brackets, with the tokens between them as children.
     Brackets,
+    /// Curly brackets, with the tokens between them as children.
     CurlyBrackets,
 
     // Symbols
+    /// The symbol `;`.
     Semicolon,
+    /// The symbol `:`.
     Colon,
+    /// The symbol `::`.
+    DoubleColon,
+    /// The symbol `,`.
     Comma,
+    /// The symbol `.`.
+    Dot,
+    /// The symbol `->`.
     RArrow,
 
+    /// The symbol `+`.
     Plus,
+    /// The symbol `-`.
     Minus,
+    /// The symbol `*`.
     Mul,
+    /// The symbol `/`.
     Div,
+    /// The symbol `!`.
     Not,
+    /// The symbol `%`.
+    Percent,
 
+    /// The symbol `||`.
     OrOr,
+    /// The symbol `&&`.
     AndAnd,
 
+    /// The symbol `&`.
+    BitAnd,
+    /// The symbol `|`.
+    BitOr,
+    /// The symbol `^`.
+    BitXor,
+    /// The symbol `~`.
+    Tilde,
+    /// The symbol `<<`.
+    Shl,
+    /// The symbol `>>`.
+    Shr,
+
+    /// The symbol `=`.
     Eq,
+    /// The symbol `+=`.
+    PlusEq,
+    /// The symbol `-=`.
+    MinusEq,
+    /// The symbol `*=`.
+    MulEq,
+    /// The symbol `/=`.
+    DivEq,
+    /// The symbol `%=`.
+    PercentEq,
+
+    /// The symbol `==`.
     EqEq,
+    /// The symbol `!=`.
     NotEq,
+    /// The symbol `<`.
     Lt,
+    /// The symbol `<=`.
     Lte,
+    /// The symbol `>`.
     Gt,
+    /// The symbol `>=`.
     Gte,
 
     // Error
+    /// Text that isn't a valid token, and why.
     Error(TokenErrorTy),
 }
 
@@ -104,12 +444,307 @@ impl TokenType {
             TokenType::Parens | TokenType::Brackets | TokenType::CurlyBrackets
         )
     }
+
+    /// How to refer to a token of this type in a message, like "an
+    /// identifier" or "`;`". Nested tokens are referred to by their opening
+    /// delimiter.
+    pub fn describe(&self) -> &'static str {
+        use TokenType as T;
+
+        match self {
+            T::Ident => "an identifier",
+            T::IntLit => "an integer literal",
+            T::FloatLit => "a float literal",
+            T::StrLit => "a string literal",
+            T::CharLit => "a character literal",
+            T::BoolLit => "a boolean literal",
+            T::Fn => "`fn`",
+            T::Let => "`let`",
+            T::If => "`if`",
+            T::Else => "`else`",
+            T::While => "`while`",
+            T::For => "`for`",
+            T::In => "`in`",
+            T::Return => "`return`",
+            T::Struct => "`struct`",
+            T::Enum => "`enum`",
+            T::Impl => "`impl`",
+            T::Parens => "`(`",
+            T::Brackets => "`[`",
+            T::CurlyBrackets => "`{`",
+            T::Semicolon => "`;`",
+            T::Colon => "`:`",
+            T::DoubleColon => "`::`",
+            T::Comma => "`,`",
+            T::Dot => "`.`",
+            T::RArrow => "`->`",
+            T::Plus => "`+`",
+            T::Minus => "`-`",
+            T::Mul => "`*`",
+            T::Div => "`/`",
+            T::Not => "`!`",
+            T::Percent => "`%`",
+            T::OrOr => "`||`",
+            T::AndAnd => "`&&`",
+            T::BitAnd => "`&`",
+            T::BitOr => "`|`",
+            T::BitXor => "`^`",
+            T::Tilde => "`~`",
+            T::Shl => "`<<`",
+            T::Shr => "`>>`",
+            T::Eq => "`=`",
+            T::PlusEq => "`+=`",
+            T::MinusEq => "`-=`",
+            T::MulEq => "`*=`",
+            T::DivEq => "`/=`",
+            T::PercentEq => "`%=`",
+            T::EqEq => "`==`",
+            T::NotEq => "`!=`",
+            T::Lt => "`<`",
+            T::Lte => "`<=`",
+            T::Gt => "`>`",
+            T::Gte => "`>=`",
+            T::Error(_) => "an invalid token",
+        }
+    }
 }
 
+/// Why the text of a `TokenType::Error` token isn't a valid token. Each of
+/// these is reported with its own diagnostic from `specifics::lex`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenErrorTy {
+    /// Characters that can't start any token.
     IllegalChar,
+
+    /// An opening delimiter that is never closed, because a closer for a
+    /// delimiter around it came first.
     UnmatchedOpenParen,
+
+    /// An opening delimiter that is still open at the end of the file.
+    UnclosedAtEof,
+
+    /// A closing delimiter without an opening one.
     UnmatchedCloseParen,
-    MismatchedParenTy { open_span: Span },
+
+    /// A closing delimiter of a different kind than the opening one.
+    MismatchedParenTy {
+        /// Where the delimiter it closes was opened.
+        open_span: Span,
+    },
+
+    /// A string literal without a closing quote.
+    UnterminatedString,
+
+    /// A string or character literal with an unknown escape sequence.
+    InvalidEscape {
+        /// The escape sequence, starting at its backslash.
+        escape_span: Span,
+    },
+
+    /// A character literal without a closing quote.
+    UnterminatedCharLit,
+
+    /// The character literal `''`.
+    EmptyCharLit,
+
+    /// A character literal with several characters in it.
+    CharLitTooLong,
+
+    /// A number literal with a digit that doesn't belong to its base.
+    InvalidDigitForBase {
+        /// The first such digit.
+        digit_span: Span,
+    },
+
+    /// A number literal with an `_` that isn't between digits.
+    MalformedNumber,
+
+    /// A block comment that is never closed.
+    UnterminatedBlockComment,
+
+    /// A delimiter nested inside too many others, whose contents are skipped.
+    NestingTooDeep,
+}
+
+impl fmt::Display for TokenErrorTy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenErrorTy::IllegalChar => "illegal character",
+            TokenErrorTy::UnmatchedOpenParen => "unmatched opening delimiter",
+            TokenErrorTy::UnclosedAtEof => "opening delimiter unclosed at the end of the file",
+            TokenErrorTy::UnmatchedCloseParen => "unmatched closing delimiter",
+            TokenErrorTy::MismatchedParenTy { .. } => "mismatched closing delimiter",
+            TokenErrorTy::UnterminatedString => "unterminated string",
+            TokenErrorTy::InvalidEscape { .. } => "invalid escape sequence",
+            TokenErrorTy::UnterminatedCharLit => "unterminated character literal",
+            TokenErrorTy::EmptyCharLit => "empty character literal",
+            TokenErrorTy::CharLitTooLong => "character literal with more than one character",
+            TokenErrorTy::InvalidDigitForBase { .. } => "invalid digit for the base",
+            TokenErrorTy::MalformedNumber => "malformed number",
+            TokenErrorTy::UnterminatedBlockComment => "unterminated block comment",
+            TokenErrorTy::NestingTooDeep => "delimiters nested too deeply",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::lex_source_with_errors, source_map::SourceMap};
+
+    /// Whether `a` and `b` lex into trees with the same contents, checking
+    /// that their hashes agree.
+    fn same_content(a: &str, b: &str) -> bool {
+        let mut source_map = SourceMap::new();
+        let a_id = source_map.add_source(String::from("a.fern"), a.to_owned());
+        let b_id = source_map.add_source(String::from("b.fern"), b.to_owned());
+        let (a_source, b_source) = (source_map.get_source(a_id), source_map.get_source(b_id));
+
+        let (a_tokens, _) = lex_source_with_errors(a_source);
+        let (b_tokens, _) = lex_source_with_errors(b_source);
+
+        a_tokens.len() == b_tokens.len()
+            && a_tokens.iter().zip(&b_tokens).all(|(a, b)| {
+                let equal = a.content_key(a_source) == b.content_key(b_source);
+                if equal {
+                    assert_eq!(a.content_hash(a_source), b.content_hash(b_source));
+                }
+                equal
+            })
+    }
+
+    #[test]
+    fn same_text_has_same_key() {
+        assert!(same_content("f(a, [1])", "  f(a, [1])"));
+    }
+
+    #[test]
+    fn changed_token_has_different_key() {
+        assert!(!same_content("f(a, [1])", "f(a, [2])"));
+    }
+
+    #[test]
+    fn error_referring_to_earlier_span() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("(]"));
+        let source = source_map.get_source(id);
+
+        let (tokens, _) = lex_source_with_errors(source);
+        let error = &tokens[0].children()[0];
+
+        assert!(matches!(
+            error.ty(),
+            TokenType::Error(TokenErrorTy::MismatchedParenTy { .. })
+        ));
+        assert!(error.content_key(source) == error.content_key(source));
+        error.content_hash(source);
+    }
+
+    #[test]
+    fn error_display_names() {
+        use TokenErrorTy as TET;
+
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("x"));
+        let span = source_map.get_source(id).span(0, 1);
+        let names = [
+            (TET::IllegalChar, "illegal character"),
+            (TET::UnmatchedOpenParen, "unmatched opening delimiter"),
+            (
+                TET::UnclosedAtEof,
+                "opening delimiter unclosed at the end of the file",
+            ),
+            (TET::UnmatchedCloseParen, "unmatched closing delimiter"),
+            (
+                TET::MismatchedParenTy { open_span: span },
+                "mismatched closing delimiter",
+            ),
+            (TET::UnterminatedString, "unterminated string"),
+            (
+                TET::InvalidEscape { escape_span: span },
+                "invalid escape sequence",
+            ),
+            (TET::UnterminatedCharLit, "unterminated character literal"),
+            (TET::EmptyCharLit, "empty character literal"),
+            (
+                TET::CharLitTooLong,
+                "character literal with more than one character",
+            ),
+            (
+                TET::InvalidDigitForBase { digit_span: span },
+                "invalid digit for the base",
+            ),
+            (TET::MalformedNumber, "malformed number"),
+            (TET::UnterminatedBlockComment, "unterminated block comment"),
+            (TET::NestingTooDeep, "delimiters nested too deeply"),
+        ];
+
+        for (error, name) in names {
+            assert_eq!(error.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn pretty_prints_nested_tokens() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("fn f(a) { b }"));
+        let source = source_map.get_source(id);
+        let (tokens, _) = lex_source_with_errors(source);
+
+        let mut out = String::new();
+        pretty_print_tokens(&tokens, source, &mut out);
+
+        let expected = "\
+Tokens {
+  tokens: [
+    Fn `fn`
+    Ident `f`
+    Parens {
+      children: [
+        Ident `a`
+      ]
+    }
+    CurlyBrackets {
+      children: [
+        Ident `b`
+      ]
+    }
+  ]
+}";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn leaves_of_nested_tokens() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("f(a, (b))"));
+        let source = source_map.get_source(id);
+        let (tokens, _) = lex_source_with_errors(source);
+
+        let leaves: Vec<&str> = tokens
+            .iter()
+            .flat_map(TokenTree::leaves)
+            .map(|leaf| source.text_of_span(leaf.span()))
+            .collect();
+        assert_eq!(leaves, ["f", "a", ",", "b"]);
+    }
+
+    #[test]
+    fn debug_string_of_nested_tokens() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("f(a, [1])"));
+        let source = source_map.get_source(id);
+        let (tokens, _) = lex_source_with_errors(source);
+
+        let debug: Vec<String> = tokens.iter().map(|t| t.debug_string(source)).collect();
+        assert_eq!(
+            debug,
+            [
+                "Ident(f)",
+                "Parens[Ident(a), Comma(,), Brackets[IntLit(1)]]"
+            ]
+        );
+    }
 }
@@ -8,48 +8,125 @@
 //! 1) The lexer, implemented in the module `lex`.
 //! 2) TODO
 
-use std::io::stdout;
+use std::{env, io::stdout, process::ExitCode};
 
-use diagnostics::Diagnostic;
-use lex::{lex_source, token::TokenTree};
-use parse::{parse_source, visit::pretty_print};
-use source_map::SourceMap;
+use diagnostics::{render_all, specifics, Diagnostic, Diagnostics};
+use lex::{
+    lex_source,
+    token::{pretty_print_tokens, TokenTree},
+};
+use parse::{parse_source, visit::pretty_print_iterative};
+use source_map::{SourceMap, STDIN_NAME};
 
 pub mod utils;
 mod diagnostics;
+mod driver;
 mod lex;
 mod parse;
+mod passes;
 mod source_map;
+#[cfg(test)]
+mod testing;
 
-type FResult<T> = Result<T, Vec<Diagnostic>>;
+/// The result of a compiler stage, which fails with everything it reported.
+type FResult<T> = Result<T, Diagnostics>;
 
-fn main() {
+fn main() -> ExitCode {
     let mut sm = SourceMap::new();
-    sm.add_source_from_file("examples/simple.fern");
 
-    match pipeline(&sm) {
-        Ok(_) => {},
-        Err(errs) => {
-            let mut out = String::new();
+    // Each argument is a file to compile, other than the options. With no
+    // files the source is read from stdin, as it is for a file named `-`.
+    let mut emit = Emit::Ast;
+    let mut filenames = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.strip_prefix("--emit=") {
+            Some("tokens") => emit = Emit::Tokens,
+            Some("ast") => emit = Emit::Ast,
+            Some(value) => {
+                let diag = specifics::driver::unknown_emit(value);
+                print_diagnostics([&diag], &sm);
+                return ExitCode::FAILURE;
+            },
+            None => filenames.push(arg),
+        }
+    }
+    if filenames.is_empty() {
+        filenames.push("-".to_owned());
+    }
 
-            for e in errs {
-                e.render(&mut out, &sm);
-                out.push('\n');
-            }
+    let mut load_errors = Diagnostics::new();
+    for filename in &filenames {
+        let loaded = match filename.as_str() {
+            "-" => sm.add_source_from_stdin(),
+            filename => sm.get_or_add_from_file(filename),
+        };
+        if let Err(e) = loaded {
+            let filename = if filename == "-" { STDIN_NAME } else { filename };
+            load_errors.push(specifics::driver::unreadable_file(filename, &e));
+        }
+    }
+    if !load_errors.is_empty() {
+        print_diagnostics(&load_errors, &sm);
+        return ExitCode::FAILURE;
+    }
+
+    let result = pipeline(&sm, emit);
+    if let Err(errs) = &result {
+        print_diagnostics(errs, &sm);
+    }
+
+    exit_code(&result)
+}
+
+/// Print `diags` to stdout as they are rendered. If that fails, for example
+/// because stdout was closed, the failure is reported on stderr instead.
+fn print_diagnostics<'a>(diags: impl IntoIterator<Item = &'a Diagnostic>, sm: &SourceMap) {
+    if let Err(e) = render_all(diags, &mut stdout().lock(), sm) {
+        eprintln!("error: couldn't print diagnostics: {e}");
+    }
+}
 
-            print!("{out}");
-        },
+/// The status the compiler exits with after `pipeline` produced `result`,
+/// which is a failure if there were any diagnostics.
+fn exit_code(result: &FResult<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
     }
 }
 
-fn pipeline(sm: &SourceMap) -> FResult<()> {
-    let mut errors = Vec::new();
+/// What the compiler prints for each source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// The token trees produced by the lexer, selected with `--emit=tokens`.
+    Tokens,
+
+    /// The parsed AST, selected with `--emit=ast`. This is the default.
+    Ast,
+}
+
+/// Print what `emit` selects for each source in `sm`, failing with the
+/// diagnostics of every source that couldn't be lexed or parsed.
+fn pipeline(sm: &SourceMap, emit: Emit) -> FResult<()> {
+    let mut errors = Diagnostics::new();
 
     for source in sm.sources() {
+        if emit == Emit::Tokens {
+            match lex_source(source) {
+                Ok(tokens) => {
+                    let mut out = String::new();
+                    pretty_print_tokens(&tokens, source, &mut out);
+                    println!("{out}");
+                },
+                Err(e) => errors.extend(e),
+            }
+            continue;
+        }
+
         match parse_source(&source) {
             Ok(parsed) => {
                 let mut out = String::new();
-                pretty_print(&parsed, source, &mut out);
+                pretty_print_iterative(&parsed, source, &mut out);
                 println!("{out}");
             },
             Err(e) => errors.extend(e),
@@ -63,3 +140,37 @@ fn pipeline(sm: &SourceMap) -> FResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_reports_every_source() {
+        let mut sm = SourceMap::new();
+        sm.add_source(String::from("a.fern"), String::from("fn a() { @ }"));
+        sm.add_source(String::from("b.fern"), String::from("fn b() {}"));
+        sm.add_source(String::from("c.fern"), String::from("fn c() { let }"));
+
+        let Err(errors) = pipeline(&sm, Emit::Ast) else {
+            panic!("the errors weren't reported");
+        };
+
+        // An error in one source doesn't stop the ones after it from being
+        // compiled.
+        let rendered: Vec<String> = errors.iter().map(|d| d.render_plain_string(&sm)).collect();
+        let [a, c] = &rendered[..] else {
+            panic!("expected an error from `a` and `c`, got {rendered:#?}");
+        };
+        assert!(a.contains(" --> a.fern:1:10\n"), "{a}");
+        assert!(c.contains(" --> c.fern:1:"), "{c}");
+    }
+
+    #[test]
+    fn exit_code_is_failure_on_errors() {
+        assert_eq!(exit_code(&Ok(())), ExitCode::SUCCESS);
+
+        let errors: Diagnostics = [Diagnostic::new("oops".to_owned())].into_iter().collect();
+        assert_eq!(exit_code(&Err(errors)), ExitCode::FAILURE);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::source_map::Span;
+use std::fmt;
 
 #[derive(Debug)]
 pub struct FileAst {
@@ -19,6 +20,13 @@ pub struct FnDeclAst {
     pub body: BlockAst,
 }
 
+impl FnDeclAst {
+    /// The whole declaration, from `fn` to the end of the body.
+    pub fn span(&self) -> Span {
+        Span::union(self.fn_kw, self.body.span())
+    }
+}
+
 #[derive(Debug)]
 pub struct FnArgAst {
     pub name: Span,
@@ -26,6 +34,13 @@ pub struct FnArgAst {
     pub ty: TypeAst,
 }
 
+impl FnArgAst {
+    /// The argument from its name to the end of its type.
+    pub fn span(&self) -> Span {
+        Span::union(self.name, self.ty.span())
+    }
+}
+
 
 #[derive(Debug)]
 pub struct FnReturnTypeAst {
@@ -33,20 +48,45 @@ pub struct FnReturnTypeAst {
     pub ty: TypeAst,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BlockAst {
+    /// The braces around the block.
+    pub braces: Span,
     pub statements: Vec<StatementAst>,
     pub return_expr: Option<ExpressionAst>,
 }
 
-#[derive(Debug)]
+impl BlockAst {
+    /// The block including its braces.
+    pub fn span(&self) -> Span {
+        self.braces
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum StatementAst {
+    /// A lone `;`, which does nothing.
     Semicolon(Span),
-    LetStatement(LetStatementAst),
+
+    /// A `let` statement declaring a variable.
+    LetStatement(Box<LetStatementAst>),
+
+    /// An expression evaluated for its side effects.
     ExpressionStatement(ExpressionStatementAst),
 }
 
-#[derive(Debug)]
+impl StatementAst {
+    /// The whole statement, including its `;` if it has one.
+    pub fn span(&self) -> Span {
+        match self {
+            StatementAst::Semicolon(span) => *span,
+            StatementAst::LetStatement(let_stmt) => let_stmt.span(),
+            StatementAst::ExpressionStatement(expr_stmt) => expr_stmt.span(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct LetStatementAst {
     pub let_kw: Span,
     pub name_ident: Span,
@@ -56,29 +96,528 @@ pub struct LetStatementAst {
     pub semicolon: Span,
 }
 
-#[derive(Debug)]
+impl LetStatementAst {
+    /// The whole statement, from `let` to `;`.
+    pub fn span(&self) -> Span {
+        Span::union(self.let_kw, self.semicolon)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TypeAnnotationAst {
     pub colon: Span,
     pub ty: TypeAst,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExpressionStatementAst {
     pub expr: ExpressionAst,
     pub semicolon: Option<Span>,
 }
 
-#[derive(Debug)]
-pub enum ExpressionAst {}
+impl ExpressionStatementAst {
+    /// The expression along with its `;`, if it has one.
+    pub fn span(&self) -> Span {
+        match self.semicolon {
+            Some(semicolon) => Span::union(self.expr.span(), semicolon),
+            None => self.expr.span(),
+        }
+    }
+}
 
-#[derive(Debug)]
+/// An expression, which produces a value.
+#[derive(Debug, Clone)]
+pub enum ExpressionAst {
+    /// Stands in for an expression that couldn't be parsed, so the code
+    /// around it can still be.
+    Error(Span),
+
+    /// A single unqualified name.
+    Ident(Span),
+
+    /// A name, possibly qualified by the modules it is in, like `a::b::c`.
+    Path(PathAst),
+
+    /// A literal value, like `1` or `true`.
+    Literal(LiteralAst),
+
+    /// A prefix operator applied to an operand, like `-a`.
+    Unary(UnaryExprAst),
+
+    /// An infix operator applied to two operands, like `a + b`.
+    Binary(BinaryExprAst),
+
+    /// A function call, like `f(a, b)`.
+    Call(CallExprAst),
+
+    /// An access of a field, like `a.b`.
+    FieldAccess(FieldAccessAst),
+
+    /// An `if`, with any `else if` and `else` branches.
+    If(Box<IfExprAst>),
+
+    /// A `while` loop.
+    While(Box<WhileExprAst>),
+
+    /// A `for` loop over the items of an iterable.
+    For(Box<ForExprAst>),
+
+    /// A `return` from the enclosing function, with or without a value.
+    Return(ReturnExprAst),
+}
+
+impl ExpressionAst {
+    /// Whether this expression ends in a block, in which case it can be used
+    /// as a statement without a `;` after it.
+    pub fn has_block(&self) -> bool {
+        matches!(
+            self,
+            ExpressionAst::If(_) | ExpressionAst::While(_) | ExpressionAst::For(_)
+        )
+    }
+
+    /// The whole expression, including any operands and blocks.
+    pub fn span(&self) -> Span {
+        match self {
+            ExpressionAst::Error(span) | ExpressionAst::Ident(span) => *span,
+            ExpressionAst::Path(path) => path.span(),
+            ExpressionAst::Literal(literal) => literal.span,
+            ExpressionAst::Unary(unary) => Span::union(unary.op.span, unary.operand.span()),
+            ExpressionAst::Binary(binary) => Span::union(binary.lhs.span(), binary.rhs.span()),
+            ExpressionAst::Call(call) => Span::union(call.callee.span(), call.parens),
+            ExpressionAst::FieldAccess(access) => Span::union(access.base.span(), access.field),
+            ExpressionAst::If(if_expr) => if_expr.span(),
+            ExpressionAst::While(while_expr) => {
+                Span::union(while_expr.while_kw, while_expr.body.span())
+            }
+            ExpressionAst::For(for_expr) => Span::union(for_expr.for_kw, for_expr.body.span()),
+            ExpressionAst::Return(return_expr) => match &return_expr.value {
+                Some(value) => Span::union(return_expr.return_kw, value.span()),
+                None => return_expr.return_kw,
+            },
+        }
+    }
+}
+
+/// A literal value written in the code, or computed from an expression by
+/// constant folding.
+#[derive(Debug, Clone, Copy)]
+pub struct LiteralAst {
+    /// The literal token, or the whole expression a folded literal replaced.
+    pub span: Span,
+
+    /// The value of the literal.
+    pub value: LiteralValue,
+}
+
+/// The value of a `LiteralAst`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralValue {
+    /// An integer.
+    Int(u64),
+
+    /// `true` or `false`.
+    Bool(bool),
+}
+
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralValue::Int(value) => write!(f, "{value}"),
+            LiteralValue::Bool(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// A prefix operator applied to an operand, like `-a`.
+#[derive(Debug, Clone)]
+pub struct UnaryExprAst {
+    /// The operator.
+    pub op: UnaryOpAst,
+
+    /// The expression the operator applies to.
+    pub operand: Box<ExpressionAst>,
+}
+
+/// A prefix operator and where it is.
+#[derive(Debug, Clone, Copy)]
+pub struct UnaryOpAst {
+    /// Which operator it is.
+    pub kind: UnaryOp,
+
+    /// The operator token.
+    pub span: Span,
+}
+
+/// The kinds of prefix operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// Negation, `-`.
+    Neg,
+
+    /// Logical or bitwise not, `!`.
+    Not,
+}
+
+impl UnaryOp {
+    /// The operator as it is written in source code.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+/// An infix operator applied to two operands, like `a + b`.
+#[derive(Debug, Clone)]
+pub struct BinaryExprAst {
+    /// The operand on the left.
+    pub lhs: Box<ExpressionAst>,
+
+    /// The operator.
+    pub op: BinaryOpAst,
+
+    /// The operand on the right.
+    pub rhs: Box<ExpressionAst>,
+}
+
+/// A function call, like `f(a, b)`.
+#[derive(Debug, Clone)]
+pub struct CallExprAst {
+    /// The expression that is called.
+    pub callee: Box<ExpressionAst>,
+
+    /// The parentheses around the arguments.
+    pub parens: Span,
+
+    /// The arguments, in order.
+    pub args: Vec<ExpressionAst>,
+}
+
+/// An access of a field, like `a.b`.
+#[derive(Debug, Clone)]
+pub struct FieldAccessAst {
+    /// The expression whose field is accessed.
+    pub base: Box<ExpressionAst>,
+
+    /// The `.` between the base and the field.
+    pub dot: Span,
+
+    /// The name of the field.
+    pub field: Span,
+}
+
+/// An infix operator and where it is.
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryOpAst {
+    /// Which operator it is.
+    pub kind: BinaryOp,
+
+    /// The operator token. For an operator the parser added itself, such as
+    /// the `&&` joining a desugared comparison chain, this is the token that
+    /// caused it to be added.
+    pub span: Span,
+}
+
+/// The kinds of infix operator. See `BinaryOp::symbol` for how each is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// Logical or.
+    Or,
+    /// Logical and.
+    And,
+
+    /// Equal to.
+    Eq,
+    /// Not equal to.
+    NotEq,
+    /// Less than.
+    Lt,
+    /// Less than or equal to.
+    Lte,
+    /// Greater than.
+    Gt,
+    /// Greater than or equal to.
+    Gte,
+
+    /// Bitwise or.
+    BitOr,
+    /// Bitwise exclusive or.
+    BitXor,
+    /// Bitwise and.
+    BitAnd,
+    /// Shift left.
+    Shl,
+    /// Shift right.
+    Shr,
+
+    /// Addition.
+    Add,
+    /// Subtraction.
+    Sub,
+    /// Multiplication.
+    Mul,
+    /// Division.
+    Div,
+    /// Remainder.
+    Rem,
+}
+
+impl BinaryOp {
+    /// How tightly this operator binds its operands. Operators with a higher
+    /// precedence are grouped first, so `a + b * c` is `a + (b * c)`. All
+    /// operators of the same precedence associate to the left, apart from
+    /// comparisons, which can't be chained.
+    pub fn precedence(self) -> u8 {
+        use BinaryOp as B;
+
+        match self {
+            B::Or => 1,
+            B::And => 2,
+            B::Eq | B::NotEq | B::Lt | B::Lte | B::Gt | B::Gte => 3,
+            B::BitOr => 4,
+            B::BitXor => 5,
+            B::BitAnd => 6,
+            B::Shl | B::Shr => 7,
+            B::Add | B::Sub => 8,
+            B::Mul | B::Div | B::Rem => 9,
+        }
+    }
+
+    /// Whether this is one of the comparison operators, which can't be chained.
+    pub fn is_comparison(self) -> bool {
+        self.precedence() == BinaryOp::Eq.precedence()
+    }
+
+    /// The operator as it is written in source code.
+    pub fn symbol(self) -> &'static str {
+        use BinaryOp as B;
+
+        match self {
+            B::Or => "||",
+            B::And => "&&",
+            B::Eq => "==",
+            B::NotEq => "!=",
+            B::Lt => "<",
+            B::Lte => "<=",
+            B::Gt => ">",
+            B::Gte => ">=",
+            B::BitOr => "|",
+            B::BitXor => "^",
+            B::BitAnd => "&",
+            B::Shl => "<<",
+            B::Shr => ">>",
+            B::Add => "+",
+            B::Sub => "-",
+            B::Mul => "*",
+            B::Div => "/",
+            B::Rem => "%",
+        }
+    }
+}
+
+/// A name qualified by the modules it is in, like `a::b::c`.
+#[derive(Debug, Clone)]
+pub struct PathAst {
+    /// The identifiers in the path, in order.
+    pub segments: Vec<Span>,
+
+    /// The `::` between each pair of segments, so there is one fewer of
+    /// these than there are segments.
+    pub separators: Vec<Span>,
+}
+
+impl PathAst {
+    /// From the first segment to the last. A path always has at least one.
+    pub fn span(&self) -> Span {
+        let last = self.segments.len() - 1;
+        Span::union(self.segments[0], self.segments[last])
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct IfExprAst {
     pub if_kw: Span,
     pub condition: ExpressionAst,
     pub body: BlockAst,
+
+    /// The `else` or `else if` after the body, if there is one.
+    pub else_branch: Option<ElseAst>,
 }
 
-#[derive(Debug)]
-pub struct TypeAst {
-    pub name_ident: Span
+impl IfExprAst {
+    /// The whole `if`, up to the end of its last branch.
+    pub fn span(&self) -> Span {
+        let end = match self.else_branch.as_ref().map(|e| &e.body) {
+            Some(ElseBodyAst::ElseIf(if_expr)) => if_expr.span(),
+            Some(ElseBodyAst::Else(block)) => block.span(),
+            None => self.body.span(),
+        };
+        Span::union(self.if_kw, end)
+    }
+
+    /// Whether this `if` produces a value, which is the case when it ends in
+    /// an `else` and every branch ends in an expression. A type checker can
+    /// use this to decide whether the branches need to agree on a type.
+    pub fn is_value_position(&self) -> bool {
+        if self.body.return_expr.is_none() {
+            return false;
+        }
+
+        let Some(else_branch) = &self.else_branch else {
+            return false;
+        };
+
+        match &else_branch.body {
+            ElseBodyAst::ElseIf(if_expr) => if_expr.is_value_position(),
+            ElseBodyAst::Else(block) => block.return_expr.is_some(),
+        }
+    }
+}
+
+/// The `else` branch of an `if`.
+#[derive(Debug, Clone)]
+pub struct ElseAst {
+    /// The `else` keyword.
+    pub else_kw: Span,
+
+    /// What comes after the `else`.
+    pub body: ElseBodyAst,
+}
+
+/// What comes after an `else`.
+#[derive(Debug, Clone)]
+pub enum ElseBodyAst {
+    /// Another `if`, as in `else if`.
+    ElseIf(Box<IfExprAst>),
+
+    /// The final block, which runs when none of the conditions hold.
+    Else(BlockAst),
+}
+
+/// A `while` loop.
+#[derive(Debug, Clone)]
+pub struct WhileExprAst {
+    /// The `while` keyword.
+    pub while_kw: Span,
+
+    /// The condition checked before each iteration.
+    pub condition: ExpressionAst,
+
+    /// The block run on each iteration.
+    pub body: BlockAst,
+}
+
+/// A `for` loop over the items of an iterable.
+#[derive(Debug, Clone)]
+pub struct ForExprAst {
+    /// The `for` keyword.
+    pub for_kw: Span,
+
+    /// The variable each item is bound to.
+    pub item_ident: Span,
+
+    /// The `in` keyword.
+    pub in_kw: Span,
+
+    /// The expression producing the items.
+    pub iterable: ExpressionAst,
+
+    /// The block run for each item.
+    pub body: BlockAst,
+}
+
+/// A `return` from the enclosing function.
+#[derive(Debug, Clone)]
+pub struct ReturnExprAst {
+    /// The `return` keyword.
+    pub return_kw: Span,
+
+    /// The value being returned, if there is one.
+    pub value: Option<Box<ExpressionAst>>,
+}
+
+/// A type, as written in an annotation.
+#[derive(Debug, Clone)]
+pub enum TypeAst {
+    /// A type named by a single identifier, like `int`.
+    Named(Span),
+
+    /// A reference to another type, like `&int`.
+    Ref(RefTypeAst),
+
+    /// An array of another type, like `[int]`.
+    Array(ArrayTypeAst),
+
+    /// A generic type applied to type arguments, like `Vec[int]`.
+    Generic(GenericTypeAst),
+
+    /// The type of a function, like `fn(int, int) -> int`.
+    FnType(FnTypeAst),
+}
+
+impl TypeAst {
+    /// The whole type, including any type arguments or return type.
+    pub fn span(&self) -> Span {
+        match self {
+            TypeAst::Named(span) => *span,
+            TypeAst::Ref(ref_ty) => Span::union(ref_ty.amp, ref_ty.inner.span()),
+            TypeAst::Array(array) => array.brackets,
+            TypeAst::Generic(generic) => Span::union(generic.name_ident, generic.brackets),
+            TypeAst::FnType(fn_ty) => match &fn_ty.ret {
+                Some(ret) => Span::union(fn_ty.fn_kw, ret.span()),
+                None => Span::union(fn_ty.fn_kw, fn_ty.parens),
+            },
+        }
+    }
+}
+
+/// A reference to another type, like `&int`.
+#[derive(Debug, Clone)]
+pub struct RefTypeAst {
+    /// The `&`.
+    pub amp: Span,
+
+    /// The type that is referred to.
+    pub inner: Box<TypeAst>,
+}
+
+/// An array of another type, like `[int]`.
+#[derive(Debug, Clone)]
+pub struct ArrayTypeAst {
+    /// The brackets around the element type.
+    pub brackets: Span,
+
+    /// The type of the elements.
+    pub elem: Box<TypeAst>,
+}
+
+/// A generic type applied to type arguments, like `Vec[int]`.
+#[derive(Debug, Clone)]
+pub struct GenericTypeAst {
+    /// The name of the generic type.
+    pub name_ident: Span,
+
+    /// The brackets around the type arguments.
+    pub brackets: Span,
+
+    /// The type arguments, in order.
+    pub args: Vec<TypeAst>,
+}
+
+/// The type of a function, like `fn(int, int) -> int`.
+#[derive(Debug, Clone)]
+pub struct FnTypeAst {
+    /// The `fn` keyword.
+    pub fn_kw: Span,
+
+    /// The parentheses around the parameter types.
+    pub parens: Span,
+
+    /// The types of the parameters, in order.
+    pub params: Vec<TypeAst>,
+
+    /// The return type, if there is an `->` after the parameters.
+    pub ret: Option<Box<TypeAst>>,
 }
@@ -30,6 +30,8 @@
 //!
 //! expr_with_block ::=
 //!     | if_expr
+//!     | while_expr
+//!     | for_expr
 //!
 //! expr_without_block ::=
 //!     | literal_expr
@@ -38,8 +40,11 @@
 //!     | fn_call_expr
 //!     | operator_expr
 //!     | paren_expr
+//!     | return_expr
 //!
-//! if_expr ::= IF expr block (ELSE IF expr block)* (ELSE block)?
+//! if_expr    ::= IF expr block (ELSE IF expr block)* (ELSE block)?
+//! while_expr ::= WHILE expr block
+//! for_expr   ::= FOR IDENT IN expr block
 //!
 //! literal_expr      ::= INT_LITERAL | BOOL_LITERAL
 //! ident_expr        ::= IDENT
@@ -47,21 +52,34 @@
 //! fn_call_expr      ::= expr L_PAREN (expr COMMA)* expr? R_PAREN
 //! paren_expr        ::= L_PAREN expr R_PAREN
 //! operator_expr     ::= expr OPERATOR expr
+//! return_expr       ::= RETURN expr?
 //!
-//! type ::= IDENT
+//! type ::=
+//!     | IDENT
+//!     | IDENT L_BRACKET (type COMMA)* type? R_BRACKET
+//!     | AMPERSAND type
+//!     | L_BRACKET type R_BRACKET
+//!     | FN L_PAREN (type COMMA)* type? R_PAREN (R_ARROW type)?
 //! ```
 //!
 
 use crate::{
-    diagnostics::{self, Diagnostic},
+    diagnostics::{self, specifics, Diagnostic, Diagnostics},
     lex::{
-        lex_source,
+        int_value, lex_source,
         token::{TokenTree, TokenType},
     },
     source_map::{Source, Span},
     FResult,
 };
-use ast::{BlockAst, DeclarationAst, FileAst, FnArgAst, FnDeclAst, FnReturnTypeAst, TypeAst};
+use ast::{
+    ArrayTypeAst, BinaryExprAst, BinaryOp, BinaryOpAst, BlockAst, CallExprAst, DeclarationAst,
+    ElseAst, ElseBodyAst, ExpressionAst, ExpressionStatementAst, FieldAccessAst, FileAst, FnArgAst,
+    FnDeclAst, FnReturnTypeAst, FnTypeAst, ForExprAst, GenericTypeAst, IfExprAst, LetStatementAst,
+    LiteralAst, LiteralValue, PathAst, RefTypeAst, ReturnExprAst, StatementAst, TypeAnnotationAst,
+    TypeAst, UnaryExprAst, UnaryOp, UnaryOpAst, WhileExprAst,
+};
+use std::fmt::Write;
 
 pub mod ast;
 pub mod visit;
@@ -74,11 +92,38 @@ enum SyncStatus {
 
 type PResult<T> = Result<T, SyncStatus>;
 
+/// How deeply expressions and types can be nested inside each other before
+/// the parser gives up on them, so parsing them can't overflow the call stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
+/// How deep an expression can get before the parser gives up on it. This also
+/// counts the levels added by chains like `a + b + c` or `else if`, which are
+/// parsed without recursion, so the passes walking the AST can't overflow the
+/// call stack either.
+pub const MAX_TREE_DEPTH: usize = 1024;
+
+/// Options that change which programs the parser accepts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Whether a chain of comparisons like `a < b < c` is accepted and
+    /// desugared into `a < b && b < c`. When this is off, chaining comparisons
+    /// is an error.
+    pub chained_comparisons: bool,
+}
+
+/// Lex and parse `source` with the default options, failing with every problem
+/// found.
 pub fn parse_source(source: &Source) -> FResult<FileAst> {
+    parse_source_with_options(source, ParseOptions::default())
+}
+
+/// Lex and parse `source` with `options`, failing with every problem found. Any
+/// lexer errors are reported without parsing.
+pub fn parse_source_with_options(source: &Source, options: ParseOptions) -> FResult<FileAst> {
     let lexed = lex_source(source)?;
 
-    let mut cursor = Cursor::new(&lexed);
-    let mut diagnostics = Vec::new();
+    let mut cursor = Cursor::new(&lexed, source, options);
+    let mut diagnostics = Diagnostics::new();
     let parsed = parse_file(&mut cursor, &mut diagnostics);
 
     match parsed {
@@ -87,7 +132,9 @@ pub fn parse_source(source: &Source) -> FResult<FileAst> {
     }
 }
 
-fn parse_file(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FileAst> {
+/// Parse declarations until the end of the tokens. Declarations that can't be
+/// parsed are reported and skipped.
+fn parse_file(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<FileAst> {
     let mut declarations = Vec::new();
 
     while !cursor.is_eof() {
@@ -100,10 +147,16 @@ fn parse_file(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FileA
     Ok(file)
 }
 
-fn parse_decl(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<DeclarationAst> {
+/// Parse a single declaration. If it can't be parsed, the cursor is moved to
+/// where the next declaration might start.
+fn parse_decl(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<DeclarationAst> {
     let decl = match () {
         _ if cursor.peek_is(TokenType::Fn) => parse_fn(cursor, diags).map(DeclarationAst::FnDecl),
-        _ => Err(SyncStatus::Unsynced),
+        _ => {
+            let found = cursor.peek();
+            diags.push(diagnostics::specifics::parse::expected_declaration(found, cursor.source));
+            Err(SyncStatus::Unsynced)
+        }
     };
 
     if let Err(SyncStatus::Unsynced) = decl {
@@ -114,10 +167,11 @@ fn parse_decl(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<Decla
     }
 }
 
-fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclAst> {
-    fn parse_fn_arg(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnArgAst> {
-        let name = cursor.pop_expect(TokenType::Ident)?;
-        let colon = cursor.pop_expect(TokenType::Colon)?;
+/// Parse a function declaration, starting at its `fn`.
+fn parse_fn(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<FnDeclAst> {
+    fn parse_fn_arg(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<FnArgAst> {
+        let name = cursor.pop_expect(TokenType::Ident, diags)?;
+        let colon = cursor.pop_expect(TokenType::Colon, diags)?;
         let ty = parse_ty(cursor, diags)?;
 
         Ok(FnArgAst {
@@ -127,9 +181,9 @@ fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclA
         })
     }
 
-    fn parse_fn_args(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<Vec<FnArgAst>> {
-        let args_tokens = cursor.pop_expect(TokenType::Parens)?;
-        let mut cursor = Cursor::new(args_tokens.children());
+    fn parse_fn_args(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<Vec<FnArgAst>> {
+        let args_tokens = cursor.pop_expect(TokenType::Parens, diags)?;
+        let mut cursor = cursor.sub_cursor(args_tokens.children());
 
         let mut args = Vec::new();
 
@@ -146,7 +200,11 @@ fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclA
         }
 
         if !cursor.is_eof() {
-            todo!();
+            let found = cursor.peek();
+            diags.push(specifics::parse::expected_comma_or_close_paren(found, cursor.source));
+
+            // The parens were already consumed so we are still synchronized.
+            return Err(SyncStatus::Synced);
         }
 
         Ok(args)
@@ -154,7 +212,7 @@ fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclA
 
     fn parse_fn_return_ty(
         cursor: &mut Cursor,
-        diags: &mut Vec<Diagnostic>,
+        diags: &mut Diagnostics,
     ) -> PResult<Option<FnReturnTypeAst>> {
         let Some(r_arrow) = cursor.pop_if(TokenType::RArrow) else {
             return Ok(None);
@@ -168,7 +226,7 @@ fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclA
     }
 
     let fn_kew = cursor.pop_assert(TokenType::Fn);
-    let name_ident = cursor.pop_expect(TokenType::Ident);
+    let name_ident = cursor.pop_expect(TokenType::Ident, diags);
     let args = parse_fn_args(cursor, diags);
     let return_ty = parse_fn_return_ty(cursor, diags);
     let body = parse_block(cursor, diags);
@@ -184,35 +242,757 @@ fn parse_fn(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<FnDeclA
     })
 }
 
-fn parse_block(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<BlockAst> {
-    // todo!()
-    cursor.pop_expect(TokenType::CurlyBrackets);
+/// Parse a block, which is a curly bracket token holding statements and
+/// possibly an expression the block evaluates to.
+fn parse_block(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<BlockAst> {
+    let block_tokens = cursor.pop_expect(TokenType::CurlyBrackets, diags)?;
+    let mut cursor = cursor.sub_cursor(block_tokens.children());
+
+    let mut statements = Vec::new();
+    let mut return_expr = None;
+
+    while !cursor.is_eof() {
+        if let Some(semicolon) = cursor.pop_if(TokenType::Semicolon) {
+            statements.push(StatementAst::Semicolon(semicolon.span()));
+            continue;
+        }
+
+        if cursor.peek_is(TokenType::Let) {
+            if let Ok(let_stmt) = parse_let(&mut cursor, diags) {
+                statements.push(StatementAst::LetStatement(Box::new(let_stmt)));
+            }
+            continue;
+        }
+
+        // An expression that ends in a block is a statement of its own, so
+        // `if c {} -1` isn't a subtraction.
+        let expr = match parse_expr_with_block(&mut cursor, diags) {
+            Some(expr) => expr,
+            None => parse_expr(&mut cursor, diags),
+        };
+        let expr = match expr {
+            Ok(expr) => expr,
+            Err(_) => {
+                // Skip the rest of the statement, including its `;`.
+                cursor.sync_to(&[TokenType::Semicolon]);
+                cursor.pop_if(TokenType::Semicolon);
+                continue;
+            }
+        };
+
+        // An expression at the very end of the block without a `;` is the
+        // value of the block.
+        let semicolon = cursor.pop_if(TokenType::Semicolon);
+        if semicolon.is_none() && cursor.is_eof() {
+            return_expr = Some(expr);
+        } else {
+            if semicolon.is_none() && !expr.has_block() {
+                cursor.report_expected(TokenType::Semicolon, diags);
+            }
+
+            statements.push(StatementAst::ExpressionStatement(ExpressionStatementAst {
+                expr,
+                semicolon: semicolon.map(TokenTree::span),
+            }));
+        }
+    }
+
+    // The braces were already consumed so we are still synchronized.
     Ok(BlockAst {
-        statements: Vec::new(),
-        return_expr: None,
+        braces: block_tokens.span(),
+        statements,
+        return_expr,
+    })
+}
+
+/// Parse a `let` statement. On failure the rest of the statement is skipped,
+/// so this is always synchronized.
+fn parse_let(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<LetStatementAst> {
+    fn parse_type_annotation(
+        cursor: &mut Cursor,
+        diags: &mut Diagnostics,
+    ) -> PResult<Option<TypeAnnotationAst>> {
+        let Some(colon) = cursor.pop_if(TokenType::Colon) else {
+            return Ok(None);
+        };
+        let ty = parse_ty(cursor, diags)?;
+
+        Ok(Some(TypeAnnotationAst {
+            colon: colon.span(),
+            ty,
+        }))
+    }
+
+    fn parse_rest(
+        cursor: &mut Cursor,
+        diags: &mut Diagnostics,
+        let_kw: Span,
+    ) -> PResult<LetStatementAst> {
+        let Some(name_ident) = cursor.pop_if(TokenType::Ident) else {
+            let found = cursor.peek_opt();
+            diags.push(specifics::parse::expected_let_name(let_kw, found, cursor.source));
+            return Err(SyncStatus::Unsynced);
+        };
+        let type_annotation = parse_type_annotation(cursor, diags)?;
+
+        let equals = cursor.pop_expect(TokenType::Eq, diags)?;
+
+        let value = parse_expr(cursor, diags)?;
+
+        let semicolon = cursor.pop_expect(TokenType::Semicolon, diags)?;
+
+        Ok(LetStatementAst {
+            let_kw,
+            name_ident: name_ident.span(),
+            type_annotation,
+            equals: equals.span(),
+            value,
+            semicolon: semicolon.span(),
+        })
+    }
+
+    let let_kw = cursor.pop_assert(TokenType::Let).span();
+
+    parse_rest(cursor, diags, let_kw).map_err(|_| {
+        cursor.sync_to(&[TokenType::Semicolon]);
+        cursor.pop_if(TokenType::Semicolon);
+        SyncStatus::Synced
+    })
+}
+
+/// Parse an expression that ends in a block if the next token starts one.
+fn parse_expr_with_block(
+    cursor: &mut Cursor,
+    diags: &mut Diagnostics,
+) -> Option<PResult<ExpressionAst>> {
+    let expr = match cursor.peek_opt()?.ty() {
+        TokenType::If => cursor
+            .nested(diags, parse_if)
+            .map(|e| ExpressionAst::If(Box::new(e))),
+        TokenType::While => cursor
+            .nested(diags, parse_while)
+            .map(|e| ExpressionAst::While(Box::new(e))),
+        TokenType::For => cursor
+            .nested(diags, parse_for)
+            .map(|e| ExpressionAst::For(Box::new(e))),
+        _ => return None,
+    };
+
+    Some(expr)
+}
+
+/// Parse an `if` along with any `else if`s chained onto it. The chain is parsed
+/// in a loop since it can be arbitrarily long.
+fn parse_if(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<IfExprAst> {
+    // The `if`s of the chain before the last one, each with the `else` after
+    // it.
+    let mut chain = Vec::new();
+
+    let mut if_expr = loop {
+        let if_kw = cursor.pop_assert(TokenType::If).span();
+        let condition = parse_expr(cursor, diags)?;
+        let body = parse_block(cursor, diags)?;
+
+        let Some(else_kw) = cursor.pop_if(TokenType::Else) else {
+            break IfExprAst {
+                if_kw,
+                condition,
+                body,
+                else_branch: None,
+            };
+        };
+
+        if cursor.peek_is(TokenType::If) {
+            cursor.deepen(else_kw, diags)?;
+            chain.push((if_kw, condition, body, else_kw.span()));
+        } else {
+            let else_body = parse_block(cursor, diags)?;
+
+            break IfExprAst {
+                if_kw,
+                condition,
+                body,
+                else_branch: Some(ElseAst {
+                    else_kw: else_kw.span(),
+                    body: ElseBodyAst::Else(else_body),
+                }),
+            };
+        }
+    };
+
+    // Put each `if` into the `else` of the one before it.
+    while let Some((if_kw, condition, body, else_kw)) = chain.pop() {
+        if_expr = IfExprAst {
+            if_kw,
+            condition,
+            body,
+            else_branch: Some(ElseAst {
+                else_kw,
+                body: ElseBodyAst::ElseIf(Box::new(if_expr)),
+            }),
+        };
+    }
+
+    Ok(if_expr)
+}
+
+/// Parse a `while` loop, starting at its `while`.
+fn parse_while(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<WhileExprAst> {
+    let while_kw = cursor.pop_assert(TokenType::While).span();
+    let condition = parse_expr(cursor, diags)?;
+    let body = parse_block(cursor, diags)?;
+
+    Ok(WhileExprAst {
+        while_kw,
+        condition,
+        body,
+    })
+}
+
+/// Parse a `for` loop, starting at its `for`.
+fn parse_for(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ForExprAst> {
+    let for_kw = cursor.pop_assert(TokenType::For).span();
+
+    let Some(item_ident) = cursor.pop_if(TokenType::Ident) else {
+        let found = cursor.peek_opt();
+        diags.push(specifics::parse::expected_loop_variable(for_kw, found, cursor.source));
+        return Err(SyncStatus::Unsynced);
+    };
+    let in_kw = cursor.pop_expect(TokenType::In, diags)?;
+
+    let iterable = parse_expr(cursor, diags)?;
+    let body = parse_block(cursor, diags)?;
+
+    Ok(ForExprAst {
+        for_kw,
+        item_ident: item_ident.span(),
+        in_kw: in_kw.span(),
+        iterable,
+        body,
+    })
+}
+
+/// Parse any expression, one level more deeply nested than the current one.
+fn parse_expr(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ExpressionAst> {
+    cursor.nested(diags, |cursor, diags| parse_binary_expr(cursor, diags, 0))
+}
+
+/// Parse an expression made of operands joined by binary operators that have
+/// a precedence higher than `min_precedence`, using precedence climbing.
+fn parse_binary_expr(
+    cursor: &mut Cursor,
+    diags: &mut Diagnostics,
+    min_precedence: u8,
+) -> PResult<ExpressionAst> {
+    let mut lhs = parse_unary_expr(cursor, diags)?;
+
+    // The last comparison at this level and its right operand, to handle
+    // chains like `a < b < c`.
+    let mut last_comparison: Option<(BinaryOpAst, ExpressionAst)> = None;
+
+    while let Some(kind) = cursor.peek_binary_op() {
+        let precedence = kind.precedence();
+        if precedence <= min_precedence {
+            break;
+        }
+
+        let op_token = cursor.pop();
+        cursor.deepen(op_token, diags)?;
+        let op = BinaryOpAst {
+            kind,
+            span: op_token.span(),
+        };
+
+        let rhs = if cursor.is_eof() || cursor.peek_is(TokenType::Semicolon) {
+            let error = specifics::parse::expected_expression_after(op_token, cursor.source);
+            let end = op.span.end().byte();
+            diags.push(error);
+            ExpressionAst::Error(cursor.source.span(end, end))
+        } else {
+            cursor.nested(diags, |cursor, diags| {
+                parse_binary_expr(cursor, diags, precedence)
+            })?
+        };
+
+        let chained_from = last_comparison.take().filter(|_| kind.is_comparison());
+        if kind.is_comparison() {
+            last_comparison = Some((op, rhs.clone()));
+        }
+
+        let Some((prev_op, middle)) = chained_from else {
+            lhs = binary_expr(lhs, op, rhs);
+            continue;
+        };
+
+        if cursor.options.chained_comparisons {
+            // `a < b < c` means `a < b && b < c`.
+            let and = BinaryOpAst {
+                kind: BinaryOp::And,
+                span: op.span,
+            };
+            lhs = binary_expr(lhs, and, binary_expr(middle, op, rhs));
+        } else {
+            diags.push(specifics::parse::chained_comparison(
+                prev_op.span,
+                op.span,
+                cursor.source,
+            ));
+            lhs = binary_expr(lhs, op, rhs);
+        }
+    }
+
+    Ok(lhs)
+}
+
+/// The expression applying `op` to `lhs` and `rhs`.
+fn binary_expr(lhs: ExpressionAst, op: BinaryOpAst, rhs: ExpressionAst) -> ExpressionAst {
+    ExpressionAst::Binary(BinaryExprAst {
+        lhs: Box::new(lhs),
+        op,
+        rhs: Box::new(rhs),
     })
 }
 
-fn parse_ty(cursor: &mut Cursor, diags: &mut Vec<Diagnostic>) -> PResult<TypeAst> {
-    // todo!()
-    let name = cursor.pop_expect(TokenType::Ident);
-    Ok(TypeAst { name_ident: name?.span() })
+/// Parse an operand of a binary operator, which may have prefix operators.
+/// These bind tighter than any binary operator but looser than calls and
+/// field accesses, so `-a.b + c` is `(-(a.b)) + c`.
+fn parse_unary_expr(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ExpressionAst> {
+    let kind = match () {
+        _ if cursor.peek_is(TokenType::Minus) => UnaryOp::Neg,
+        _ if cursor.peek_is(TokenType::Not) => UnaryOp::Not,
+        _ => return parse_postfix_expr(cursor, diags),
+    };
+
+    let op_token = cursor.pop();
+    let op = UnaryOpAst {
+        kind,
+        span: op_token.span(),
+    };
+
+    let operand = if cursor.is_eof() || cursor.peek_is(TokenType::Semicolon) {
+        let error = specifics::parse::expected_expression_after(op_token, cursor.source);
+        let end = op.span.end().byte();
+        diags.push(error);
+        ExpressionAst::Error(cursor.source.span(end, end))
+    } else {
+        cursor.nested(diags, parse_unary_expr)?
+    };
+
+    Ok(ExpressionAst::Unary(UnaryExprAst {
+        op,
+        operand: Box::new(operand),
+    }))
+}
+
+/// Parse an operand along with any calls and field accesses that follow it.
+fn parse_postfix_expr(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ExpressionAst> {
+    let mut expr = parse_primary_expr(cursor, diags)?;
+
+    loop {
+        if let Some(parens) = cursor.pop_if(TokenType::Parens) {
+            cursor.deepen(parens, diags)?;
+            let args = parse_call_args(&mut cursor.sub_cursor(parens.children()), diags);
+
+            expr = ExpressionAst::Call(CallExprAst {
+                callee: Box::new(expr),
+                parens: parens.span(),
+                args,
+            });
+        } else if let Some(dot) = cursor.pop_if(TokenType::Dot) {
+            cursor.deepen(dot, diags)?;
+            let Some(field) = cursor.pop_if(TokenType::Ident) else {
+                diags.push(specifics::parse::expected_field_name(dot, cursor.source));
+                return Err(SyncStatus::Unsynced);
+            };
+
+            expr = ExpressionAst::FieldAccess(FieldAccessAst {
+                base: Box::new(expr),
+                dot: dot.span(),
+                field: field.span(),
+            });
+        } else {
+            break;
+        }
+    }
+
+    Ok(expr)
+}
+
+/// Parse the comma separated arguments inside the parentheses of a call. An
+/// argument that can't be parsed is left out.
+fn parse_call_args(cursor: &mut Cursor, diags: &mut Diagnostics) -> Vec<ExpressionAst> {
+    let mut args = Vec::new();
+
+    while !cursor.is_eof() {
+        match parse_expr(cursor, diags) {
+            Ok(arg) => args.push(arg),
+            Err(_) => cursor.sync_to(&[TokenType::Comma]),
+        }
+
+        if cursor.pop_if(TokenType::Comma).is_none() {
+            break;
+        }
+    }
+
+    if !cursor.is_eof() {
+        let found = cursor.peek();
+        diags.push(specifics::parse::expected_comma_or_close_paren(found, cursor.source));
+    }
+
+    args
+}
+
+/// Parse an expression that doesn't contain any operators.
+fn parse_primary_expr(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ExpressionAst> {
+    if let Some(expr) = parse_expr_with_block(cursor, diags) {
+        return expr;
+    }
+
+    let Some(next) = cursor.peek_opt() else {
+        cursor.report_end("an expression", diags);
+        return Err(SyncStatus::Unsynced);
+    };
+
+    let expr = match next.ty() {
+        TokenType::IntLit | TokenType::BoolLit => parse_literal(cursor, diags),
+        TokenType::Ident => parse_ident_or_path(cursor),
+        TokenType::Parens => parse_paren_expr(cursor, diags),
+        TokenType::Return => parse_return(cursor, diags)?,
+        _ => {
+            diags.push(specifics::parse::expected_expression(next, cursor.source));
+            return Err(SyncStatus::Unsynced);
+        }
+    };
+
+    Ok(expr)
+}
+
+/// Parse a `return`, which returns the value of everything after it up to the
+/// end of the statement, if there is anything.
+fn parse_return(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<ExpressionAst> {
+    let return_kw = cursor.pop_assert(TokenType::Return).span();
+
+    let value = if cursor.is_eof() || cursor.peek_is(TokenType::Semicolon) {
+        None
+    } else {
+        Some(Box::new(parse_expr(cursor, diags)?))
+    };
+
+    Ok(ExpressionAst::Return(ReturnExprAst { return_kw, value }))
+}
+
+/// Parse an integer or boolean literal. If the literal doesn't have a valid
+/// value we report it and return an error expression in its place.
+fn parse_literal(cursor: &mut Cursor, diags: &mut Diagnostics) -> ExpressionAst {
+    let token = cursor.pop();
+    let span = token.span();
+
+    let value = match token.ty() {
+        TokenType::IntLit => match int_value(span, cursor.source) {
+            Ok(value) => LiteralValue::Int(value),
+            Err(error) => {
+                diags.push(error);
+                return ExpressionAst::Error(span);
+            }
+        },
+        TokenType::BoolLit => LiteralValue::Bool(cursor.source.text_of_span(span) == "true"),
+        _ => unreachable!("Only call parse_literal on literal tokens."),
+    };
+
+    ExpressionAst::Literal(LiteralAst { span, value })
+}
+
+/// Parse a name, which is a path if it is followed by `::`.
+fn parse_ident_or_path(cursor: &mut Cursor) -> ExpressionAst {
+    let first = cursor.pop_assert(TokenType::Ident).span();
+
+    let mut segments = vec![first];
+    let mut separators = Vec::new();
+
+    while cursor.peek_is(TokenType::DoubleColon) && cursor.peek_nth_is(1, TokenType::Ident) {
+        separators.push(cursor.pop().span());
+        segments.push(cursor.pop().span());
+    }
+
+    if separators.is_empty() {
+        ExpressionAst::Ident(first)
+    } else {
+        ExpressionAst::Path(PathAst {
+            segments,
+            separators,
+        })
+    }
+}
+
+/// Parse an expression in parentheses, returning the inner expression. Since
+/// the parentheses are a single token, any problem inside them is reported
+/// and replaced with an error expression without losing synchronization.
+fn parse_paren_expr(cursor: &mut Cursor, diags: &mut Diagnostics) -> ExpressionAst {
+    let parens = cursor.pop_assert(TokenType::Parens);
+    let mut cursor = cursor.sub_cursor(parens.children());
+
+    if cursor.is_eof() {
+        diags.push(specifics::parse::empty_parens(parens.span(), cursor.source));
+        return ExpressionAst::Error(parens.span());
+    }
+
+    let Ok(expr) = parse_expr(&mut cursor, diags) else {
+        return ExpressionAst::Error(parens.span());
+    };
+
+    if !cursor.is_eof() {
+        let found = cursor.peek();
+        diags.push(specifics::parse::expected_close_paren(found, cursor.source));
+    }
+
+    expr
+}
+
+/// Parse a type. See the `type` rule of the grammar for what types look like.
+fn parse_ty(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<TypeAst> {
+    match cursor.peek_opt().map(|token| token.ty()) {
+        Some(TokenType::BitAnd) => {
+            let amp = cursor.pop().span();
+            let inner = cursor.nested(diags, parse_ty)?;
+
+            Ok(TypeAst::Ref(RefTypeAst {
+                amp,
+                inner: Box::new(inner),
+            }))
+        }
+        Some(TokenType::AndAnd) => {
+            // `&&T` is lexed as a single token but is a reference to a
+            // reference, so split it into its two `&`s.
+            let start = cursor.pop().span().start().byte();
+            let inner = cursor.nested(diags, parse_ty)?;
+            let inner = TypeAst::Ref(RefTypeAst {
+                amp: cursor.source.span(start + 1, start + 2),
+                inner: Box::new(inner),
+            });
+
+            Ok(TypeAst::Ref(RefTypeAst {
+                amp: cursor.source.span(start, start + 1),
+                inner: Box::new(inner),
+            }))
+        }
+        Some(TokenType::Brackets) => parse_array_ty(cursor, diags),
+        Some(TokenType::Fn) if cursor.peek_nth_is(1, TokenType::Parens) => {
+            parse_fn_ty(cursor, diags)
+        }
+        Some(TokenType::Ident) => parse_named_ty(cursor, diags),
+        Some(_) => {
+            let found = cursor.peek();
+            diags.push(specifics::parse::expected_type(found, cursor.source));
+            Err(SyncStatus::Unsynced)
+        }
+        None => {
+            cursor.report_expected(TokenType::Ident, diags);
+            Err(SyncStatus::Unsynced)
+        }
+    }
+}
+
+/// Parse an array type like `[int]`. The brackets are a single token, so a
+/// problem inside them leaves us synchronized after them.
+fn parse_array_ty(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<TypeAst> {
+    let brackets = cursor.pop_assert(TokenType::Brackets);
+    let mut cursor = cursor.sub_cursor(brackets.children());
+
+    if cursor.is_eof() {
+        diags.push(specifics::parse::empty_type_brackets(brackets.span(), cursor.source));
+        return Err(SyncStatus::Synced);
+    }
+
+    let elem = parse_ty(&mut cursor, diags).map_err(|_| SyncStatus::Synced)?;
+
+    if !cursor.is_eof() {
+        let found = cursor.peek();
+        diags.push(specifics::parse::expected_close_bracket(found, cursor.source));
+        return Err(SyncStatus::Synced);
+    }
+
+    Ok(TypeAst::Array(ArrayTypeAst {
+        brackets: brackets.span(),
+        elem: Box::new(elem),
+    }))
+}
+
+/// Parse a function type like `fn(int, int) -> int`. Without an `->` the
+/// function doesn't return anything.
+fn parse_fn_ty(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<TypeAst> {
+    let fn_kw = cursor.pop_assert(TokenType::Fn).span();
+    let parens = cursor.pop_assert(TokenType::Parens);
+
+    let mut params_cursor = cursor.sub_cursor(parens.children());
+    let mut params = Vec::new();
+
+    while !params_cursor.is_eof() {
+        match parse_ty(&mut params_cursor, diags) {
+            Ok(param) => params.push(param),
+            Err(_) => params_cursor.sync_to(&[TokenType::Comma]),
+        }
+
+        if params_cursor.pop_if(TokenType::Comma).is_none() {
+            break;
+        }
+    }
+
+    if !params_cursor.is_eof() {
+        let found = params_cursor.peek();
+        diags.push(specifics::parse::expected_comma_or_close_paren(found, cursor.source));
+    }
+
+    let ret = match cursor.pop_if(TokenType::RArrow) {
+        Some(_) => Some(Box::new(cursor.nested(diags, parse_ty)?)),
+        None => None,
+    };
+
+    Ok(TypeAst::FnType(FnTypeAst {
+        fn_kw,
+        parens: parens.span(),
+        params,
+        ret,
+    }))
+}
+
+/// Parse a type name, along with its type arguments if it is followed by
+/// brackets like `Vec[int]`.
+fn parse_named_ty(cursor: &mut Cursor, diags: &mut Diagnostics) -> PResult<TypeAst> {
+    let name_ident = cursor.pop_assert(TokenType::Ident).span();
+
+    let Some(brackets) = cursor.pop_if(TokenType::Brackets) else {
+        return Ok(TypeAst::Named(name_ident));
+    };
+    let mut cursor = cursor.sub_cursor(brackets.children());
+
+    if cursor.is_eof() {
+        diags.push(specifics::parse::empty_type_brackets(brackets.span(), cursor.source));
+        return Err(SyncStatus::Synced);
+    }
+
+    let mut args = Vec::new();
+
+    while !cursor.is_eof() {
+        match parse_ty(&mut cursor, diags) {
+            Ok(arg) => args.push(arg),
+            Err(_) => cursor.sync_to(&[TokenType::Comma]),
+        }
+
+        if cursor.pop_if(TokenType::Comma).is_none() {
+            break;
+        }
+    }
+
+    if !cursor.is_eof() {
+        let found = cursor.peek();
+        diags.push(specifics::parse::expected_comma_or_close_bracket(found, cursor.source));
+    }
+
+    Ok(TypeAst::Generic(GenericTypeAst {
+        name_ident,
+        brackets: brackets.span(),
+        args,
+    }))
 }
 
 struct Cursor<'a> {
     tokens: &'a [TokenTree],
     pos: usize,
+
+    /// The source the tokens are from.
+    source: &'a Source,
+
+    /// Which programs are accepted.
+    options: ParseOptions,
+
+    /// Whether a missing token at the end of `tokens` was already reported.
+    eof_reported: bool,
+
+    /// How many levels of expressions and types we are inside of.
+    depth: usize,
+
+    /// How deep the expression or type being parsed is. See `MAX_TREE_DEPTH`.
+    tree_depth: usize,
 }
 
 impl<'a> Cursor<'a> {
-    fn new(tokens: &'a [TokenTree]) -> Self {
-        Self { tokens, pos: 0 }
+    /// A cursor at the start of `tokens`, at the top level of nesting.
+    fn new(tokens: &'a [TokenTree], source: &'a Source, options: ParseOptions) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            source,
+            options,
+            eof_reported: false,
+            depth: 0,
+            tree_depth: 0,
+        }
+    }
+
+    /// Create a cursor over the children of a nested token from this cursor.
+    /// It is as deeply nested as this cursor.
+    fn sub_cursor(&self, tokens: &'a [TokenTree]) -> Self {
+        Self {
+            depth: self.depth,
+            tree_depth: self.tree_depth,
+            ..Self::new(tokens, self.source, self.options)
+        }
+    }
+
+    /// Parse something one level more deeply nested with `parse`. Beyond
+    /// `MAX_NESTING_DEPTH` levels this is reported instead and nothing is
+    /// parsed.
+    fn nested<T>(
+        &mut self,
+        diags: &mut Diagnostics,
+        parse: impl FnOnce(&mut Self, &mut Diagnostics) -> PResult<T>,
+    ) -> PResult<T> {
+        if self.depth >= MAX_NESTING_DEPTH || self.tree_depth >= MAX_TREE_DEPTH {
+            let span = match self.peek_opt() {
+                Some(token) => token.span(),
+                None => self.tokens[self.pos - 1].span(),
+            };
+            diags.push(specifics::parse::nesting_too_deep(span, self.source));
+            return Err(SyncStatus::Unsynced);
+        }
+
+        // Any chain `parse` goes through is finished once it returns, so the
+        // tree depth goes back to what it was.
+        let tree_depth = self.tree_depth;
+        self.depth += 1;
+        self.tree_depth += 1;
+        let parsed = parse(self, diags);
+        self.depth -= 1;
+        self.tree_depth = tree_depth;
+
+        parsed
+    }
+
+    /// Note that the expression being parsed got a level deeper at `token`
+    /// without any recursion, like at the operator that continues a chain of
+    /// them. Beyond `MAX_TREE_DEPTH` levels this is reported instead.
+    fn deepen(&mut self, token: &TokenTree, diags: &mut Diagnostics) -> PResult<()> {
+        if self.tree_depth >= MAX_TREE_DEPTH {
+            diags.push(specifics::parse::nesting_too_deep(
+                token.span(),
+                self.source,
+            ));
+            return Err(SyncStatus::Unsynced);
+        }
+
+        self.tree_depth += 1;
+        Ok(())
     }
 
     fn peek(&self) -> &'a TokenTree {
         &self.tokens[self.pos]
     }
 
+    /// The next token, or `None` at the end of the tokens.
+    fn peek_opt(&self) -> Option<&'a TokenTree> {
+        self.tokens.get(self.pos)
+    }
+
     fn pop(&mut self) -> &'a TokenTree {
         assert!(!self.is_eof());
 
@@ -224,6 +1004,11 @@ impl<'a> Cursor<'a> {
         !self.is_eof() && self.peek().ty() == ty
     }
 
+    /// Whether the token `n` tokens after the current one has type `ty`.
+    fn peek_nth_is(&self, n: usize, ty: TokenType) -> bool {
+        self.tokens.get(self.pos + n).is_some_and(|token| token.ty() == ty)
+    }
+
     fn is_eof(&self) -> bool {
         self.pos >= self.tokens.len()
     }
@@ -233,19 +1018,652 @@ impl<'a> Cursor<'a> {
         self.pop()
     }
 
-    fn pop_expect(&mut self, ty: TokenType) -> PResult<&'a TokenTree> {
+    /// Take the next token if it has type `ty`. Otherwise that is reported and
+    /// nothing is taken.
+    fn pop_expect(&mut self, ty: TokenType, diags: &mut Diagnostics) -> PResult<&'a TokenTree> {
         if self.peek_is(ty) {
             Ok(self.pop())
         } else {
+            self.report_expected(ty, diags);
             Err(SyncStatus::Unsynced)
         }
     }
 
+    /// Report that a token of type `ty` was expected next.
+    fn report_expected(&mut self, ty: TokenType, diags: &mut Diagnostics) {
+        match self.peek_opt() {
+            Some(found) => diags.push(specifics::parse::unexpected_token(ty, found, self.source)),
+            None => self.report_end(ty.describe(), diags),
+        }
+    }
+
+    /// Report that the tokens ran out where `expected` should have been. This
+    /// is only reported once, since everything expected after that is missing
+    /// too.
+    fn report_end(&mut self, expected: &str, diags: &mut Diagnostics) {
+        if self.eof_reported {
+            return;
+        }
+
+        let prev = self.tokens[self.pos - 1].span();
+        diags.push(specifics::parse::unexpected_end(expected, prev, self.source));
+        self.eof_reported = true;
+    }
+
     fn pop_if(&mut self, ty: TokenType) -> Option<&'a TokenTree> {
         self.peek_is(ty).then(|| self.pop())
     }
 
+
+    /// The binary operator the next token is, if it is one.
+    fn peek_binary_op(&self) -> Option<BinaryOp> {
+        use BinaryOp as B;
+
+        if self.is_eof() {
+            return None;
+        }
+
+        let op = match self.peek().ty() {
+            TokenType::OrOr => B::Or,
+            TokenType::AndAnd => B::And,
+            TokenType::EqEq => B::Eq,
+            TokenType::NotEq => B::NotEq,
+            TokenType::Lt => B::Lt,
+            TokenType::Lte => B::Lte,
+            TokenType::Gt => B::Gt,
+            TokenType::Gte => B::Gte,
+            TokenType::BitOr => B::BitOr,
+            TokenType::BitXor => B::BitXor,
+            TokenType::BitAnd => B::BitAnd,
+            TokenType::Shl => B::Shl,
+            TokenType::Shr => B::Shr,
+            TokenType::Plus => B::Add,
+            TokenType::Minus => B::Sub,
+            TokenType::Mul => B::Mul,
+            TokenType::Div => B::Div,
+            TokenType::Percent => B::Rem,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
     fn sync_to(&mut self, sync_tokens: &[TokenType]) {
-        while !self.is_eof() && !sync_tokens.contains(&self.peek().ty()) {}
+        while !self.is_eof() && !sync_tokens.contains(&self.peek().ty()) {
+            self.pop();
+        }
+    }
+
+    /// Describe up to `n` tokens on either side of the current position along
+    /// with their text. The current token is marked with `>`.
+    ///
+    /// This is only meant as a debugging aid.
+    fn debug_context(&self, n: usize) -> String {
+        let start = self.pos.saturating_sub(n);
+        let end = (self.pos + n + 1).min(self.tokens.len());
+
+        let mut out = String::new();
+        for (i, token) in self.tokens[start..end].iter().enumerate() {
+            let marker = if start + i == self.pos { '>' } else { ' ' };
+            let text = self.source.text_of_span(token.span());
+            writeln!(out, "{marker} {:?} `{text}`", token.ty()).unwrap();
+        }
+
+        if self.is_eof() {
+            writeln!(out, "> <eof>").unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+
+    /// Parse `text` as a file of its own, returning the AST if there is one
+    /// along with everything reported while parsing it, rendered without
+    /// colors.
+    fn parse(text: &str) -> (Option<FileAst>, Vec<String>) {
+        parse_with_options(text, ParseOptions::default())
+    }
+
+    /// Parse `text` like `parse` does, with `options`.
+    fn parse_with_options(text: &str, options: ParseOptions) -> (Option<FileAst>, Vec<String>) {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+        let source = source_map.get_source(id);
+
+        let Ok(tokens) = lex_source(source) else {
+            panic!("`{text}` has lexer errors");
+        };
+        let mut cursor = Cursor::new(&tokens, source, options);
+        let mut diags = Diagnostics::new();
+        let file = parse_file(&mut cursor, &mut diags).ok();
+        let messages = diags
+            .iter()
+            .map(|diag| diag.render_plain_string(&source_map))
+            .collect();
+
+        (file, messages)
+    }
+
+    /// Parse `text` as the body of a function, which must not have errors,
+    /// and show how the expression it ends in is grouped by putting every
+    /// operation in parentheses.
+    fn grouped(text: &str) -> String {
+        let text = format!("fn f() {{ {text} }}");
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.clone());
+        let source = source_map.get_source(id);
+
+        let Ok(file) = parse_source(source) else {
+            panic!("`{text}` has errors");
+        };
+        let DeclarationAst::FnDecl(fn_decl) = &file.declarations[0];
+        group(fn_decl.body.return_expr.as_ref().unwrap(), source)
+    }
+
+    /// Write out `expr` with every unary and binary operation in parentheses.
+    fn group(expr: &ExpressionAst, source: &Source) -> String {
+        match expr {
+            ExpressionAst::Unary(unary) => {
+                let op = unary.op.kind.symbol();
+                format!("({op}{})", group(&unary.operand, source))
+            }
+            ExpressionAst::Binary(binary) => format!(
+                "({} {} {})",
+                group(&binary.lhs, source),
+                source.text_of_span(binary.op.span),
+                group(&binary.rhs, source)
+            ),
+            ExpressionAst::Call(call) => {
+                let args: Vec<String> = call.args.iter().map(|a| group(a, source)).collect();
+                format!("{}({})", group(&call.callee, source), args.join(", "))
+            }
+            ExpressionAst::FieldAccess(access) => format!(
+                "{}.{}",
+                group(&access.base, source),
+                source.text_of_span(access.field)
+            ),
+            _ => source.text_of_span(expr.span()).to_owned(),
+        }
+    }
+
+    #[test]
+    fn recovers_at_next_fn() {
+        let (file, messages) = parse("x y fn f() {}");
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(file.unwrap().declarations.len(), 1);
+    }
+
+    #[test]
+    fn suggests_declaration_keyword() {
+        let (_, messages) = parse("fnn f() {}");
+
+        assert!(messages[0].contains("did you mean `fn`?"), "{}", messages[0]);
+    }
+
+    #[test]
+    fn only_suggests_declaration_keywords() {
+        for text in ["i", "fals"] {
+            let (_, messages) = parse(text);
+
+            assert!(!messages[0].contains("did you mean"), "{}", messages[0]);
+        }
+    }
+
+    #[test]
+    fn missing_comma_between_args() {
+        let (_, messages) = parse("fn f(a: int b: int) {}");
+
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn too_deeply_nested() {
+        let text = format!("fn f() {{ {}1; }} fn g() {{}}", "-".repeat(10_000));
+        let (file, messages) = parse(&text);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(file.unwrap().declarations.len(), 2);
+    }
+
+    #[test]
+    fn missing_operand_after_operator() {
+        let (file, messages) = parse("fn f() { let x = 1 +; x }");
+
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("after `+`"), "{}", messages[0]);
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        assert_eq!(fn_decl.body.statements.len(), 1);
+        assert!(fn_decl.body.return_expr.is_some());
+    }
+
+    #[test]
+    fn missing_operand_after_return_value() {
+        let (file, messages) = parse("fn f() { return a *; }");
+
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("after `*`"), "{}", messages[0]);
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let StatementAst::ExpressionStatement(stmt) = &fn_decl.body.statements[0] else {
+            panic!("`return a *;` isn't an expression statement");
+        };
+        assert!(matches!(stmt.expr, ExpressionAst::Return(_)));
+    }
+
+    #[test]
+    fn let_without_value() {
+        let (_, messages) = parse("fn f() { let x = }");
+
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn if_without_condition() {
+        let (_, messages) = parse("fn f() { if }");
+
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn while_without_condition() {
+        let (_, messages) = parse("fn f() { while }");
+
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn for_without_iterable() {
+        let (_, messages) = parse("fn f() { for i in }");
+
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn debug_context_marks_current_token() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("a + b * c"));
+        let source = source_map.get_source(id);
+        let tokens = lex_source(source).unwrap();
+
+        let mut cursor = Cursor::new(&tokens, source, ParseOptions::default());
+        cursor.pop();
+        cursor.pop();
+
+        assert_eq!(
+            cursor.debug_context(1),
+            "  Plus `+`\n> Ident `b`\n  Mul `*`\n"
+        );
+    }
+
+    #[test]
+    fn unclosed_body_is_reported_once() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("fn f() {"));
+
+        let diags = parse_source(source_map.get_source(id)).unwrap_err();
+
+        assert_eq!(diags.len(), 1);
+        let rendered = diags
+            .iter()
+            .next()
+            .unwrap()
+            .render_plain_string(&source_map);
+        assert!(rendered.contains("This `{` is never closed."), "{rendered}");
+        assert!(
+            rendered.contains("reached the end of the file"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn chained_comparison_is_an_error_by_default() {
+        let (_, messages) = parse("fn f() { a < b < c }");
+
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("can't be chained"), "{}", messages[0]);
+    }
+
+    #[test]
+    fn chained_comparison_is_desugared_when_enabled() {
+        let options = ParseOptions {
+            chained_comparisons: true,
+        };
+        let (file, messages) = parse_with_options("fn f() { a < b <= c }", options);
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let Some(ExpressionAst::Binary(and)) = &fn_decl.body.return_expr else {
+            panic!("the chain wasn't desugared into a binary expression");
+        };
+        assert_eq!(and.op.kind, BinaryOp::And);
+        let (ExpressionAst::Binary(lhs), ExpressionAst::Binary(rhs)) = (&*and.lhs, &*and.rhs)
+        else {
+            panic!("the operands of `&&` aren't comparisons");
+        };
+        assert_eq!((lhs.op.kind, rhs.op.kind), (BinaryOp::Lt, BinaryOp::Lte));
+        assert_eq!(lhs.rhs.span(), rhs.lhs.span());
+    }
+
+    #[test]
+    fn if_with_else_is_value_position() {
+        let (file, messages) = parse("fn f() { let x = if c { 1 } else { 2 }; if c { 1 } }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let StatementAst::LetStatement(let_stmt) = &fn_decl.body.statements[0] else {
+            panic!("the first statement isn't a `let`");
+        };
+        let ExpressionAst::If(if_expr) = &let_stmt.value else {
+            panic!("the value of `x` isn't an `if`");
+        };
+        assert!(if_expr.is_value_position());
+
+        // Without an `else` there is no value when the condition is false.
+        let Some(ExpressionAst::If(if_expr)) = &fn_decl.body.return_expr else {
+            panic!("the body doesn't end in an `if`");
+        };
+        assert!(!if_expr.is_value_position());
+    }
+
+    #[test]
+    fn call_through_path() {
+        let (file, messages) = parse("fn f() { a::b::c(1) }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let Some(ExpressionAst::Call(call)) = &fn_decl.body.return_expr else {
+            panic!("the body doesn't end in a call");
+        };
+        let ExpressionAst::Path(path) = &*call.callee else {
+            panic!("the callee isn't a path");
+        };
+        let segments: Vec<_> = path.segments.iter().map(|s| s.byte_range()).collect();
+        assert_eq!(segments, [9..10, 12..13, 15..16]);
+        assert_eq!(call.args.len(), 1);
+    }
+
+    #[test]
+    fn block_statements_and_return_expr() {
+        let (file, messages) = parse("fn f() { let x = 1; g(x); ; x }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let block = &fn_decl.body;
+        assert!(matches!(
+            block.statements[..],
+            [
+                StatementAst::LetStatement(_),
+                StatementAst::ExpressionStatement(ExpressionStatementAst {
+                    expr: ExpressionAst::Call(_),
+                    semicolon: Some(_),
+                }),
+                StatementAst::Semicolon(_),
+            ]
+        ));
+        assert!(matches!(block.return_expr, Some(ExpressionAst::Ident(_))));
+    }
+
+    #[test]
+    fn binary_precedence() {
+        assert_eq!(grouped("1 + 2 * 3"), "(1 + (2 * 3))");
+        assert_eq!(grouped("1 * 2 + 3"), "((1 * 2) + 3)");
+        assert_eq!(grouped("a - b - c"), "((a - b) - c)");
+        assert_eq!(grouped("a == b && c"), "((a == b) && c)");
+        assert_eq!(grouped("a || b && c < d"), "(a || (b && (c < d)))");
+    }
+
+    #[test]
+    fn literal_expressions() {
+        for (text, value) in [
+            ("42", LiteralValue::Int(42)),
+            ("true", LiteralValue::Bool(true)),
+        ] {
+            let (file, messages) = parse(&format!("fn f() {{ {text} }}"));
+
+            assert!(messages.is_empty(), "{messages:?}");
+            let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+            let Some(ExpressionAst::Literal(literal)) = fn_decl.body.return_expr else {
+                panic!("`{text}` isn't a literal");
+            };
+            assert_eq!(literal.value, value);
+            assert_eq!(literal.span.byte_range(), 9..9 + text.len());
+        }
+    }
+
+    #[test]
+    fn ident_and_paren_expressions() {
+        assert_eq!(grouped("foo"), "foo");
+        assert_eq!(grouped("(1 + 2)"), "(1 + 2)");
+        assert_eq!(grouped("(1 + 2) * 3"), "((1 + 2) * 3)");
+
+        let (_, messages) = parse("fn f() { () }");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Expected an expression inside the parentheses."));
+
+        let (_, messages) = parse("fn f() { (1 2) }");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Expected `)`, found `2`."));
+    }
+
+    #[test]
+    fn call_expressions() {
+        assert_eq!(grouped("f()"), "f()");
+        assert_eq!(grouped("f(1, 2)"), "f(1, 2)");
+        assert_eq!(grouped("f(1,)"), "f(1)");
+        assert_eq!(grouped("f(a + 1)(b)()"), "f((a + 1))(b)()");
+    }
+
+    #[test]
+    fn field_access_expressions() {
+        assert_eq!(grouped("x.y"), "x.y");
+        assert_eq!(grouped("x.y.z"), "x.y.z");
+        assert_eq!(grouped("obj.method()"), "obj.method()");
+        assert_eq!(grouped("a.b.c() + d.e"), "(a.b.c() + d.e)");
+    }
+
+    #[test]
+    fn unary_expressions() {
+        assert_eq!(grouped("-x"), "(-x)");
+        assert_eq!(grouped("!flag"), "(!flag)");
+        assert_eq!(grouped("!!x"), "(!(!x))");
+        assert_eq!(grouped("-a + b"), "((-a) + b)");
+        assert_eq!(grouped("-a.b()"), "(-a.b())");
+    }
+
+    #[test]
+    fn let_statements() {
+        let (file, messages) = parse("fn f() { let x = 1; let y: int = 2; }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let [StatementAst::LetStatement(x), StatementAst::LetStatement(y)] =
+            &fn_decl.body.statements[..]
+        else {
+            panic!("the body isn't two `let` statements");
+        };
+        assert!(x.type_annotation.is_none());
+        let ty = &y.type_annotation.as_ref().unwrap().ty;
+        assert!(matches!(ty, TypeAst::Named(_)));
+        assert_eq!(ty.span().byte_range(), 27..30);
+    }
+
+    #[test]
+    fn let_without_semicolon_or_equals() {
+        // Each error is reported once, and parsing carries on after the next
+        // `;`.
+        let (file, messages) = parse("fn f() { let x = 1 let y = 2; }");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Expected `;`, found `let`."));
+        assert!(file.is_some());
+
+        let (_, messages) = parse("fn f() { let x 1; let y = ; }");
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("Expected `=`, found `1`."));
+    }
+
+    #[test]
+    fn if_else_chains() {
+        let cases = [
+            ("if a {}", (1, false)),
+            ("if a {} else {}", (1, true)),
+            ("if a { 1 } else if b { 2 } else { 3 }", (2, true)),
+        ];
+
+        for (text, shape) in cases {
+            let (file, messages) = parse(&format!("fn f() {{ {text} }}"));
+
+            assert!(messages.is_empty(), "{messages:?}");
+            let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+            let Some(ExpressionAst::If(if_expr)) = &fn_decl.body.return_expr else {
+                panic!("`{text}` isn't an `if`");
+            };
+
+            // Follow the `else if`s, counting the `if`s in the chain.
+            let mut if_expr: &IfExprAst = if_expr;
+            let mut ifs = 1;
+            let has_else = loop {
+                match if_expr.else_branch.as_ref().map(|e| &e.body) {
+                    Some(ElseBodyAst::ElseIf(next)) => {
+                        if_expr = next;
+                        ifs += 1;
+                    }
+                    Some(ElseBodyAst::Else(_)) => break true,
+                    None => break false,
+                }
+            };
+            assert_eq!((ifs, has_else), shape, "parsing `{text}`");
+        }
+    }
+
+    #[test]
+    fn while_loop() {
+        let (file, messages) = parse("fn f() { while x < 10 { } }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let Some(ExpressionAst::While(while_expr)) = &fn_decl.body.return_expr else {
+            panic!("the body doesn't end in a `while`");
+        };
+        let ExpressionAst::Binary(condition) = &while_expr.condition else {
+            panic!("the condition isn't a comparison");
+        };
+        assert_eq!(condition.op.kind, BinaryOp::Lt);
+        assert_eq!(while_expr.condition.span().byte_range(), 15..21);
+        assert_eq!(while_expr.body.span().byte_range(), 22..25);
+    }
+
+    #[test]
+    fn for_loop() {
+        let (file, messages) = parse("fn f() { for i in items { } }");
+
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+        let Some(ExpressionAst::For(for_expr)) = &fn_decl.body.return_expr else {
+            panic!("the body doesn't end in a `for`");
+        };
+        assert_eq!(for_expr.item_ident.byte_range(), 13..14);
+        assert!(matches!(for_expr.iterable, ExpressionAst::Ident(_)));
+        assert_eq!(for_expr.iterable.span().byte_range(), 18..23);
+        assert_eq!(for_expr.body.span().byte_range(), 24..27);
+    }
+
+    #[test]
+    fn fn_without_name() {
+        for text in ["fn", "fn () {}"] {
+            let (_, messages) = parse(text);
+
+            assert_eq!(messages.len(), 1, "parsing `{text}`");
+            let message = &messages[0];
+            assert!(message.contains("expected an identifier"), "{message}");
+        }
+    }
+
+    #[test]
+    fn malformed_fn_args() {
+        let cases = [
+            ("fn f(a: int b: int) {}", "Expected `,` or `)`, found `b`."),
+            ("fn f(a: int,,) {}", "Expected an identifier, found `,`."),
+        ];
+
+        for (text, expected) in cases {
+            let (file, messages) = parse(text);
+
+            assert_eq!(messages.len(), 1, "parsing `{text}`");
+            let message = &messages[0];
+            assert!(message.contains(expected), "{message}");
+            assert!(file.is_some());
+        }
+    }
+
+    #[test]
+    fn type_expressions() {
+        // The type of the only argument of a function, which starts at byte 8.
+        let arg_ty = |ty: &str| {
+            let (file, messages) = parse(&format!("fn f(x: {ty}) {{}}"));
+            assert!(messages.is_empty(), "{messages:?}");
+            let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+            fn_decl.args[0].ty.clone()
+        };
+        let range = |ty: &TypeAst| ty.span().byte_range();
+
+        let TypeAst::Named(name) = arg_ty("int") else {
+            panic!("`int` isn't a named type");
+        };
+        assert_eq!(name.byte_range(), 8..11);
+
+        let TypeAst::Ref(ref_ty) = arg_ty("&int") else {
+            panic!("`&int` isn't a reference type");
+        };
+        assert_eq!(ref_ty.amp.byte_range(), 8..9);
+        assert!(matches!(*ref_ty.inner, TypeAst::Named(_)));
+        assert_eq!(range(&ref_ty.inner), 9..12);
+
+        let TypeAst::Array(array) = arg_ty("[int]") else {
+            panic!("`[int]` isn't an array type");
+        };
+        assert_eq!(array.brackets.byte_range(), 8..13);
+        assert_eq!(range(&array.elem), 9..12);
+
+        let TypeAst::Generic(generic) = arg_ty("Map[int, &bool]") else {
+            panic!("`Map[int, &bool]` isn't a generic type");
+        };
+        assert_eq!(generic.name_ident.byte_range(), 8..11);
+        assert!(matches!(
+            generic.args[..],
+            [TypeAst::Named(_), TypeAst::Ref(_)]
+        ));
+        assert_eq!(range(&generic.args[1]), 17..22);
+
+        let (_, messages) = parse("fn f(x: [], y: 1) {}");
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("Expected a type inside the brackets."));
+        assert!(messages[1].contains("Expected a type, found `1`."));
+    }
+
+    #[test]
+    fn fn_types() {
+        let (file, messages) = parse("fn f(g: fn(int) -> bool, h: fn()) {}");
+        assert!(messages.is_empty(), "{messages:?}");
+        let DeclarationAst::FnDecl(fn_decl) = &file.unwrap().declarations[0];
+
+        let TypeAst::FnType(g) = &fn_decl.args[0].ty else {
+            panic!("`fn(int) -> bool` isn't a function type");
+        };
+        assert_eq!(g.fn_kw.byte_range(), 8..10);
+        assert!(matches!(g.params[..], [TypeAst::Named(_)]));
+        assert_eq!(g.params[0].span().byte_range(), 11..14);
+        assert_eq!(g.ret.as_ref().unwrap().span().byte_range(), 19..23);
+        assert_eq!(fn_decl.args[0].ty.span().byte_range(), 8..23);
+
+        let TypeAst::FnType(h) = &fn_decl.args[1].ty else {
+            panic!("`fn()` isn't a function type");
+        };
+        assert!(h.params.is_empty());
+        assert!(h.ret.is_none());
     }
 }
@@ -1,11 +1,18 @@
 use core::fmt;
 use std::fmt::{DebugStruct, Write};
 
-use crate::{source_map::Source, utils::tree_writer::TreePrinter};
+use crate::{
+    source_map::{Source, Span},
+    utils::{
+        json::write_string,
+        tree_writer::{print_tree, print_tree_iterative, TreeField, TreeShape},
+    },
+};
 
 use super::ast::{
-    BlockAst, DeclarationAst, ExpressionAst, ExpressionStatementAst, FileAst, FnArgAst, FnDeclAst,
-    FnReturnTypeAst, IfExprAst, LetStatementAst, StatementAst, TypeAnnotationAst, TypeAst,
+    BlockAst, DeclarationAst, ElseBodyAst, ExpressionAst, ExpressionStatementAst, FileAst,
+    FnArgAst, FnDeclAst, FnReturnTypeAst, IfExprAst, LetStatementAst, StatementAst,
+    TypeAnnotationAst, TypeAst,
 };
 
 trait AstVisitor<T> {
@@ -24,44 +31,610 @@ trait AstVisitor<T> {
     fn visit_ty(&mut self, ty: &TypeAst) -> T;
 }
 
+/// Like `AstVisitor` but with mutable access to the nodes, for passes that
+/// transform the AST. Every method walks the children of its node by
+/// default, so a pass only needs to override the nodes it cares about. An
+/// override can call the matching `walk_*` function to still visit the
+/// children.
+pub trait AstVisitorMut {
+    /// Visit a whole file.
+    fn visit_file(&mut self, file: &mut FileAst) {
+        walk_file(self, file);
+    }
+
+    /// Visit a declaration of any kind.
+    fn visit_decl(&mut self, decl: &mut DeclarationAst) {
+        walk_decl(self, decl);
+    }
+
+    /// Visit a function declaration.
+    fn visit_fn_decl(&mut self, fn_decl: &mut FnDeclAst) {
+        walk_fn_decl(self, fn_decl);
+    }
+
+    /// Visit an argument of a function declaration.
+    fn visit_fn_arg(&mut self, fn_arg: &mut FnArgAst) {
+        walk_fn_arg(self, fn_arg);
+    }
+
+    /// Visit the return type of a function declaration, which is `None` if it
+    /// has none.
+    fn visit_fn_ret_ty(&mut self, fn_ret_ty: &mut Option<FnReturnTypeAst>) {
+        walk_fn_ret_ty(self, fn_ret_ty);
+    }
+
+    /// Visit a block.
+    fn visit_block(&mut self, block: &mut BlockAst) {
+        walk_block(self, block);
+    }
+
+    /// Visit a statement of any kind.
+    fn visit_statement(&mut self, stmt: &mut StatementAst) {
+        walk_statement(self, stmt);
+    }
+
+    /// Visit a `let` statement.
+    fn visit_let_statement(&mut self, let_stmt: &mut LetStatementAst) {
+        walk_let_statement(self, let_stmt);
+    }
+
+    /// Visit the type annotation of a `let` statement.
+    fn visit_type_annotation(&mut self, type_annotation: &mut TypeAnnotationAst) {
+        walk_type_annotation(self, type_annotation);
+    }
+
+    /// Visit an expression used as a statement.
+    fn visit_expr_stmt(&mut self, expr_stmt: &mut ExpressionStatementAst) {
+        walk_expr_stmt(self, expr_stmt);
+    }
+
+    /// Visit an expression of any kind.
+    fn visit_expr(&mut self, expr: &mut ExpressionAst) {
+        walk_expr(self, expr);
+    }
+
+    /// Visit an `if`, including any `else if` and `else` branches.
+    fn visit_if_expr(&mut self, if_expr: &mut IfExprAst) {
+        walk_if_expr(self, if_expr);
+    }
+
+    /// Visit a type.
+    fn visit_ty(&mut self, ty: &mut TypeAst) {
+        walk_ty(self, ty);
+    }
+}
+
+/// Visit each declaration of `file`.
+pub fn walk_file<V: AstVisitorMut + ?Sized>(v: &mut V, file: &mut FileAst) {
+    for decl in &mut file.declarations {
+        v.visit_decl(decl);
+    }
+}
+
+/// Visit the specific kind of declaration `decl` is.
+pub fn walk_decl<V: AstVisitorMut + ?Sized>(v: &mut V, decl: &mut DeclarationAst) {
+    match decl {
+        DeclarationAst::FnDecl(fn_decl) => v.visit_fn_decl(fn_decl),
+    }
+}
+
+/// Visit the arguments, return type and body of `fn_decl`.
+pub fn walk_fn_decl<V: AstVisitorMut + ?Sized>(v: &mut V, fn_decl: &mut FnDeclAst) {
+    for arg in &mut fn_decl.args {
+        v.visit_fn_arg(arg);
+    }
+    v.visit_fn_ret_ty(&mut fn_decl.return_ty);
+    v.visit_block(&mut fn_decl.body);
+}
+
+/// Visit the type of `fn_arg`.
+pub fn walk_fn_arg<V: AstVisitorMut + ?Sized>(v: &mut V, fn_arg: &mut FnArgAst) {
+    v.visit_ty(&mut fn_arg.ty);
+}
+
+/// Visit the return type, if there is one.
+pub fn walk_fn_ret_ty<V: AstVisitorMut + ?Sized>(
+    v: &mut V,
+    fn_ret_ty: &mut Option<FnReturnTypeAst>,
+) {
+    if let Some(fn_ret_ty) = fn_ret_ty {
+        v.visit_ty(&mut fn_ret_ty.ty);
+    }
+}
+
+/// Visit each statement of `block` and then the expression it ends in, if any.
+pub fn walk_block<V: AstVisitorMut + ?Sized>(v: &mut V, block: &mut BlockAst) {
+    for stmt in &mut block.statements {
+        v.visit_statement(stmt);
+    }
+    if let Some(expr) = &mut block.return_expr {
+        v.visit_expr(expr);
+    }
+}
+
+/// Visit the specific kind of statement `stmt` is. A lone `;` has nothing to
+/// visit.
+pub fn walk_statement<V: AstVisitorMut + ?Sized>(v: &mut V, stmt: &mut StatementAst) {
+    match stmt {
+        StatementAst::Semicolon(_) => {}
+        StatementAst::LetStatement(let_stmt) => v.visit_let_statement(let_stmt),
+        StatementAst::ExpressionStatement(expr_stmt) => v.visit_expr_stmt(expr_stmt),
+    }
+}
+
+/// Visit the type annotation of `let_stmt`, if it has one, and then its value.
+pub fn walk_let_statement<V: AstVisitorMut + ?Sized>(v: &mut V, let_stmt: &mut LetStatementAst) {
+    if let Some(type_annotation) = &mut let_stmt.type_annotation {
+        v.visit_type_annotation(type_annotation);
+    }
+    v.visit_expr(&mut let_stmt.value);
+}
+
+/// Visit the type of `type_annotation`.
+pub fn walk_type_annotation<V: AstVisitorMut + ?Sized>(
+    v: &mut V,
+    type_annotation: &mut TypeAnnotationAst,
+) {
+    v.visit_ty(&mut type_annotation.ty);
+}
+
+/// Visit each type `ty` is made of, such as the element type of an array.
+pub fn walk_ty<V: AstVisitorMut + ?Sized>(v: &mut V, ty: &mut TypeAst) {
+    match ty {
+        TypeAst::Named(_) => {}
+        TypeAst::Ref(ref_ty) => v.visit_ty(&mut ref_ty.inner),
+        TypeAst::Array(array) => v.visit_ty(&mut array.elem),
+        TypeAst::Generic(generic) => {
+            for arg in &mut generic.args {
+                v.visit_ty(arg);
+            }
+        }
+        TypeAst::FnType(fn_ty) => {
+            for param in &mut fn_ty.params {
+                v.visit_ty(param);
+            }
+            if let Some(ret) = &mut fn_ty.ret {
+                v.visit_ty(ret);
+            }
+        }
+    }
+}
+
+/// Visit the expression of `expr_stmt`.
+pub fn walk_expr_stmt<V: AstVisitorMut + ?Sized>(
+    v: &mut V,
+    expr_stmt: &mut ExpressionStatementAst,
+) {
+    v.visit_expr(&mut expr_stmt.expr);
+}
+
+/// Visit each subexpression of `expr` in the order they appear, along with the
+/// blocks of loops.
+pub fn walk_expr<V: AstVisitorMut + ?Sized>(v: &mut V, expr: &mut ExpressionAst) {
+    match expr {
+        ExpressionAst::Error(_)
+        | ExpressionAst::Ident(_)
+        | ExpressionAst::Path(_)
+        | ExpressionAst::Literal(_) => {}
+        ExpressionAst::Unary(unary) => v.visit_expr(&mut unary.operand),
+        ExpressionAst::Binary(binary) => {
+            v.visit_expr(&mut binary.lhs);
+            v.visit_expr(&mut binary.rhs);
+        }
+        ExpressionAst::Call(call) => {
+            v.visit_expr(&mut call.callee);
+            for arg in &mut call.args {
+                v.visit_expr(arg);
+            }
+        }
+        ExpressionAst::FieldAccess(access) => v.visit_expr(&mut access.base),
+        ExpressionAst::If(if_expr) => v.visit_if_expr(if_expr),
+        ExpressionAst::While(while_expr) => {
+            v.visit_expr(&mut while_expr.condition);
+            v.visit_block(&mut while_expr.body);
+        }
+        ExpressionAst::For(for_expr) => {
+            v.visit_expr(&mut for_expr.iterable);
+            v.visit_block(&mut for_expr.body);
+        }
+        ExpressionAst::Return(return_expr) => {
+            if let Some(value) = &mut return_expr.value {
+                v.visit_expr(value);
+            }
+        }
+    }
+}
+
+/// Visit the condition and body of `if_expr`, then its `else` branch, if it has
+/// one.
+pub fn walk_if_expr<V: AstVisitorMut + ?Sized>(v: &mut V, if_expr: &mut IfExprAst) {
+    v.visit_expr(&mut if_expr.condition);
+    v.visit_block(&mut if_expr.body);
+
+    if let Some(else_branch) = &mut if_expr.else_branch {
+        match &mut else_branch.body {
+            ElseBodyAst::ElseIf(if_expr) => v.visit_if_expr(if_expr),
+            ElseBodyAst::Else(block) => v.visit_block(block),
+        }
+    }
+}
+
+/// Print `file` as a tree with a node on each line, followed by its fields.
 pub fn pretty_print(file: &FileAst, source: &Source, wr: &mut dyn fmt::Write) {
-    let mut pp = PrettyPrintAst { source };
+    let mut pp = PrettyPrintAst::new(source);
+    let text = pp.visit_file(file);
+    write!(wr, "{text}");
+}
+
+/// Print the same output as `pretty_print` with the location of each node
+/// added, for debugging the spans the parser produces.
+pub fn pretty_print_with_spans(file: &FileAst, source: &Source, wr: &mut dyn fmt::Write) {
+    let mut pp = PrettyPrintAst::new(source).with_spans(true);
     let text = pp.visit_file(file);
     write!(wr, "{text}");
 }
 
+/// Print the same output as `pretty_print` without recursing for each level of
+/// the tree, so even very deeply nested ASTs can be printed.
+pub fn pretty_print_iterative(file: &FileAst, source: &Source, wr: &mut dyn fmt::Write) {
+    let pp = PrettyPrintAst::new(source);
+    let text = print_tree_iterative(AstNode::File(file), &mut |n| pp.shape(n));
+    write!(wr, "{text}");
+}
+
+/// A reference to any node of the AST that `PrettyPrintAst` prints.
+#[derive(Clone, Copy)]
+enum AstNode<'a> {
+    /// A whole file.
+    File(&'a FileAst),
+
+    /// A declaration of any kind.
+    Decl(&'a DeclarationAst),
+
+    /// A function declaration.
+    FnDecl(&'a FnDeclAst),
+
+    /// An argument of a function declaration.
+    FnArg(&'a FnArgAst),
+
+    /// A block.
+    Block(&'a BlockAst),
+
+    /// A statement of any kind.
+    Stmt(&'a StatementAst),
+
+    /// A `let` statement.
+    Let(&'a LetStatementAst),
+
+    /// An expression used as a statement.
+    ExprStmt(&'a ExpressionStatementAst),
+
+    /// An expression.
+    Expr(&'a ExpressionAst),
+
+    /// An `if`, with its branches.
+    If(&'a IfExprAst),
+
+    /// A type.
+    Ty(&'a TypeAst),
+}
+
+impl<'a> AstNode<'a> {
+    /// The span of the node, if it has one.
+    fn span(self) -> Option<Span> {
+        use AstNode as N;
+
+        match self {
+            N::File(_) => None,
+            N::Decl(DeclarationAst::FnDecl(fn_decl)) | N::FnDecl(fn_decl) => Some(fn_decl.span()),
+            N::FnArg(fn_arg) => Some(fn_arg.span()),
+            N::Block(block) => Some(block.span()),
+            N::Stmt(stmt) => Some(stmt.span()),
+            N::Let(let_stmt) => Some(let_stmt.span()),
+            N::ExprStmt(expr_stmt) => Some(expr_stmt.span()),
+            N::Expr(expr) => Some(expr.span()),
+            N::If(if_expr) => Some(if_expr.span()),
+            N::Ty(ty) => Some(ty.span()),
+        }
+    }
+}
+
+/// Prints the AST as a tree for debugging, as `pretty_print` does.
 pub struct PrettyPrintAst<'a> {
+    /// The source the AST was parsed from, which names and literals are looked
+    /// up in.
     source: &'a Source,
+
+    /// Whether each node is printed along with its location.
+    show_spans: bool,
+}
+
+impl<'a> PrettyPrintAst<'a> {
+    /// A printer that doesn't show locations.
+    pub fn new(source: &'a Source) -> Self {
+        Self {
+            source,
+            show_spans: false,
+        }
+    }
+
+    /// Set whether each node is printed with its location, as
+    /// `line:col-line:col`.
+    pub fn with_spans(mut self, enabled: bool) -> Self {
+        self.show_spans = enabled;
+        self
+    }
+
+    /// Print the tree rooted at `node`.
+    fn print(&self, node: AstNode) -> String {
+        print_tree(node, &mut |n| self.shape(n))
+    }
+
+    /// Describe how `node` is printed. This is shared by the recursive and
+    /// iterative printers so they always agree.
+    fn shape<'n>(&self, node: AstNode<'n>) -> TreeShape<AstNode<'n>> {
+        let shape = self.shape_without_span(node);
+
+        // Finding the span of an expression recurses into its operands, so
+        // only do it when it is shown.
+        if !self.show_spans {
+            return shape;
+        }
+        let Some(span) = node.span() else {
+            return shape;
+        };
+
+        // Leaves have the location after their text, while nodes have it as
+        // their first field.
+        let location = self.location(span);
+        match shape {
+            TreeShape::Leaf(text) => TreeShape::Leaf(format!("{text} @ {location}")),
+            TreeShape::Node { name, mut fields } => {
+                fields.insert(0, ("span", TreeField::Text(location)));
+                TreeShape::Node { name, fields }
+            }
+        }
+    }
+
+    /// Describe how `node` is printed, ignoring `show_spans`.
+    fn shape_without_span<'n>(&self, node: AstNode<'n>) -> TreeShape<AstNode<'n>> {
+        use AstNode as N;
+        use TreeField as F;
+
+        let (name, fields) = match node {
+            N::File(file) => (
+                "File",
+                vec![
+                    ("filename", F::Text(self.source.filename().to_owned())),
+                    ("declarations", F::List(file.declarations.iter().map(N::Decl).collect())),
+                ],
+            ),
+            N::Decl(DeclarationAst::FnDecl(fn_decl)) => {
+                return self.shape_without_span(N::FnDecl(fn_decl))
+            }
+            N::FnDecl(fn_decl) => {
+                let ret_ty = match &fn_decl.return_ty {
+                    Some(ret_ty) => F::Child(N::Ty(&ret_ty.ty)),
+                    None => F::Text(String::from("()")),
+                };
+
+                (
+                    "FnDecl",
+                    vec![
+                        ("name", F::Text(self.text(fn_decl.name_ident))),
+                        ("args", F::List(fn_decl.args.iter().map(N::FnArg).collect())),
+                        ("ret_ty", ret_ty),
+                        ("body", F::Child(N::Block(&fn_decl.body))),
+                    ],
+                )
+            }
+            N::FnArg(fn_arg) => (
+                "FnArg",
+                vec![
+                    ("name", F::Text(self.text(fn_arg.name))),
+                    ("ty", F::Child(N::Ty(&fn_arg.ty))),
+                ],
+            ),
+            N::Block(block) => {
+                let statements = block.statements.iter().map(N::Stmt).collect();
+                let return_expr = match &block.return_expr {
+                    Some(expr) => F::Child(N::Expr(expr)),
+                    None => F::Text(String::from("None")),
+                };
+
+                (
+                    "Block",
+                    vec![
+                        ("statements", F::List(statements)),
+                        ("return_expr", return_expr),
+                    ],
+                )
+            }
+            N::Stmt(stmt) => {
+                return match stmt {
+                    StatementAst::Semicolon(_) => TreeShape::Leaf(String::from("Semicolon")),
+                    StatementAst::LetStatement(let_stmt) => {
+                        self.shape_without_span(N::Let(let_stmt))
+                    }
+                    StatementAst::ExpressionStatement(expr_stmt) => {
+                        self.shape_without_span(N::ExprStmt(expr_stmt))
+                    }
+                }
+            }
+            N::Let(let_stmt) => {
+                let ty = match &let_stmt.type_annotation {
+                    Some(type_annotation) => F::Child(N::Ty(&type_annotation.ty)),
+                    None => F::Text(String::from("None")),
+                };
+
+                (
+                    "Let",
+                    vec![
+                        ("name", F::Text(self.text(let_stmt.name_ident))),
+                        ("ty", ty),
+                        ("value", F::Child(N::Expr(&let_stmt.value))),
+                    ],
+                )
+            }
+            N::ExprStmt(expr_stmt) => {
+                let semicolon = expr_stmt.semicolon.is_some().to_string();
+
+                (
+                    "ExprStmt",
+                    vec![
+                        ("expr", F::Child(N::Expr(&expr_stmt.expr))),
+                        ("semicolon", F::Text(semicolon)),
+                    ],
+                )
+            }
+            N::Expr(expr) => match expr {
+                ExpressionAst::Error(_) => return TreeShape::Leaf(String::from("Error")),
+                ExpressionAst::Ident(span) => return TreeShape::Leaf(self.text(*span)),
+                ExpressionAst::Path(path) => {
+                    let segments: Vec<String> =
+                        path.segments.iter().map(|s| self.text(*s)).collect();
+                    return TreeShape::Leaf(segments.join("::"));
+                }
+                ExpressionAst::Literal(literal) => {
+                    return TreeShape::Leaf(literal.value.to_string())
+                }
+                ExpressionAst::Unary(unary) => (
+                    "Unary",
+                    vec![
+                        ("op", F::Text(unary.op.kind.symbol().to_owned())),
+                        ("operand", F::Child(N::Expr(&unary.operand))),
+                    ],
+                ),
+                ExpressionAst::Binary(binary) => (
+                    "Binary",
+                    vec![
+                        ("op", F::Text(binary.op.kind.symbol().to_owned())),
+                        ("lhs", F::Child(N::Expr(&binary.lhs))),
+                        ("rhs", F::Child(N::Expr(&binary.rhs))),
+                    ],
+                ),
+                ExpressionAst::Call(call) => (
+                    "Call",
+                    vec![
+                        ("callee", F::Child(N::Expr(&call.callee))),
+                        ("args", F::List(call.args.iter().map(N::Expr).collect())),
+                    ],
+                ),
+                ExpressionAst::FieldAccess(access) => (
+                    "FieldAccess",
+                    vec![
+                        ("base", F::Child(N::Expr(&access.base))),
+                        ("field", F::Text(self.text(access.field))),
+                    ],
+                ),
+                ExpressionAst::If(if_expr) => return self.shape_without_span(N::If(if_expr)),
+                ExpressionAst::While(while_expr) => (
+                    "While",
+                    vec![
+                        ("condition", F::Child(N::Expr(&while_expr.condition))),
+                        ("body", F::Child(N::Block(&while_expr.body))),
+                    ],
+                ),
+                ExpressionAst::For(for_expr) => (
+                    "For",
+                    vec![
+                        ("item", F::Text(self.text(for_expr.item_ident))),
+                        ("iterable", F::Child(N::Expr(&for_expr.iterable))),
+                        ("body", F::Child(N::Block(&for_expr.body))),
+                    ],
+                ),
+                ExpressionAst::Return(return_expr) => {
+                    let value = match &return_expr.value {
+                        Some(value) => F::Child(N::Expr(value)),
+                        None => F::Text(String::from("None")),
+                    };
+
+                    ("Return", vec![("value", value)])
+                }
+            },
+            N::If(if_expr) => {
+                let else_branch = match &if_expr.else_branch {
+                    Some(else_branch) => match &else_branch.body {
+                        ElseBodyAst::ElseIf(if_expr) => F::Child(N::If(if_expr)),
+                        ElseBodyAst::Else(block) => F::Child(N::Block(block)),
+                    },
+                    None => F::Text(String::from("None")),
+                };
+
+                (
+                    "If",
+                    vec![
+                        ("condition", F::Child(N::Expr(&if_expr.condition))),
+                        ("body", F::Child(N::Block(&if_expr.body))),
+                        ("else", else_branch),
+                    ],
+                )
+            }
+            N::Ty(ty) => match ty {
+                TypeAst::Named(span) => return TreeShape::Leaf(self.text(*span)),
+                TypeAst::Ref(ref_ty) => ("Ref", vec![("inner", F::Child(N::Ty(&ref_ty.inner)))]),
+                TypeAst::Array(array) => ("Array", vec![("elem", F::Child(N::Ty(&array.elem)))]),
+                TypeAst::Generic(generic) => (
+                    "Generic",
+                    vec![
+                        ("name", F::Text(self.text(generic.name_ident))),
+                        ("args", F::List(generic.args.iter().map(N::Ty).collect())),
+                    ],
+                ),
+                TypeAst::FnType(fn_ty) => {
+                    let ret = match &fn_ty.ret {
+                        Some(ret) => F::Child(N::Ty(ret)),
+                        None => F::Text(String::from("()")),
+                    };
+
+                    (
+                        "FnType",
+                        vec![
+                            ("params", F::List(fn_ty.params.iter().map(N::Ty).collect())),
+                            ("ret", ret),
+                        ],
+                    )
+                }
+            },
+        };
+
+        TreeShape::Node { name, fields }
+    }
+
+    /// The source text of `span`.
+    fn text(&self, span: Span) -> String {
+        self.source.text_of_span(span).to_owned()
+    }
+
+    /// The location of `span` as `line:col-line:col`, where the end is
+    /// exclusive.
+    fn location(&self, span: Span) -> String {
+        let (start, end) = (span.start(), span.end());
+        format!(
+            "{}:{}-{}:{}",
+            self.source.line_of(start),
+            self.source.col_of(start),
+            self.source.line_of(end),
+            self.source.col_of(end)
+        )
+    }
 }
 
 impl<'a> AstVisitor<String> for PrettyPrintAst<'a> {
     fn visit_file(&mut self, file: &FileAst) -> String {
-        TreePrinter::start("File")
-            .field("filename", self.source.filename())
-            .field_list("declarations", &file.declarations, |d| self.visit_decl(d))
-            .finish()
+        self.print(AstNode::File(file))
     }
 
     fn visit_decl(&mut self, decl: &DeclarationAst) -> String {
-        match decl {
-            DeclarationAst::FnDecl(fn_decl_ast) => self.visit_fn_decl(fn_decl_ast),
-        }
+        self.print(AstNode::Decl(decl))
     }
 
     fn visit_fn_decl(&mut self, fn_decl: &FnDeclAst) -> String {
-        TreePrinter::start("FnDecl")
-            .field("name", self.source.text_of_span(fn_decl.name_ident))
-            .field_list("args", &fn_decl.args, |a| self.visit_fn_arg(a))
-            .field("ret_ty", self.visit_fn_ret_ty(&fn_decl.return_ty))
-            .field("body", self.visit_block(&fn_decl.body))
-            .finish()
+        self.print(AstNode::FnDecl(fn_decl))
     }
 
     fn visit_fn_arg(&mut self, fn_arg: &FnArgAst) -> String {
-        TreePrinter::start("FnArg")
-            .field("name", self.source.text_of_span(fn_arg.name))
-            .field("ty", self.visit_ty(&fn_arg.ty))
-            .finish()
+        self.print(AstNode::FnArg(fn_arg))
     }
 
     fn visit_fn_ret_ty(&mut self, fn_ret_ty: &Option<FnReturnTypeAst>) -> String {
@@ -73,34 +646,922 @@ impl<'a> AstVisitor<String> for PrettyPrintAst<'a> {
     }
 
     fn visit_block(&mut self, block: &BlockAst) -> String {
-        String::from("Block")
+        self.print(AstNode::Block(block))
     }
 
     fn visit_statement(&mut self, stmt: &StatementAst) -> String {
-        todo!()
+        self.print(AstNode::Stmt(stmt))
     }
 
     fn visit_let_statement(&mut self, let_stmt: &LetStatementAst) -> String {
-        todo!()
+        self.print(AstNode::Let(let_stmt))
     }
 
     fn visit_type_annotation(&mut self, type_annotation: &TypeAnnotationAst) -> String {
-        todo!()
+        self.visit_ty(&type_annotation.ty)
     }
 
     fn visit_expr_stmt(&mut self, expr_stmt: &ExpressionStatementAst) -> String {
-        todo!()
+        self.print(AstNode::ExprStmt(expr_stmt))
     }
 
     fn visit_expr(&mut self, expr: &ExpressionAst) -> String {
-        todo!()
+        self.print(AstNode::Expr(expr))
     }
 
     fn visit_if_expr(&mut self, if_expr: &IfExprAst) -> String {
-        todo!()
+        self.print(AstNode::If(if_expr))
     }
 
     fn visit_ty(&mut self, ty: &TypeAst) -> String {
-        self.source.text_of_span(ty.name_ident).into()
+        self.print(AstNode::Ty(ty))
+    }
+}
+
+/// Reconstructs source code from the AST. Expressions are only parenthesized
+/// where precedence requires it, so this doubles as a check that the parser
+/// grouped them correctly.
+pub struct ExprSourcePrinter<'a> {
+    /// The source the AST was parsed from.
+    source: &'a Source,
+}
+
+impl<'a> ExprSourcePrinter<'a> {
+    /// A printer for code from `source`.
+    pub fn new(source: &'a Source) -> Self {
+        Self { source }
+    }
+
+    /// The source text of `span`.
+    fn text(&self, span: Span) -> &'a str {
+        self.source.text_of_span(span)
+    }
+}
+
+/// Whether `expr` needs parentheses when it is called or has a field accessed.
+/// Without them, operators, `return` and the last block of an `if` or loop
+/// would take the call or field access into themselves.
+fn needs_parens_before_postfix(expr: &ExpressionAst) -> bool {
+    matches!(
+        expr,
+        ExpressionAst::Binary(_)
+            | ExpressionAst::Unary(_)
+            | ExpressionAst::Return(_)
+            | ExpressionAst::If(_)
+            | ExpressionAst::While(_)
+            | ExpressionAst::For(_)
+    )
+}
+
+/// Indent every non-empty line of `text` by one level.
+fn indent_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("    {line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl<'a> AstVisitor<String> for ExprSourcePrinter<'a> {
+    fn visit_file(&mut self, file: &FileAst) -> String {
+        let decls: Vec<String> = file
+            .declarations
+            .iter()
+            .map(|d| self.visit_decl(d))
+            .collect();
+        decls.join("\n\n")
+    }
+
+    fn visit_decl(&mut self, decl: &DeclarationAst) -> String {
+        match decl {
+            DeclarationAst::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl),
+        }
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &FnDeclAst) -> String {
+        let args: Vec<String> = fn_decl.args.iter().map(|a| self.visit_fn_arg(a)).collect();
+
+        format!(
+            "fn {}({}){} {}",
+            self.text(fn_decl.name_ident),
+            args.join(", "),
+            self.visit_fn_ret_ty(&fn_decl.return_ty),
+            self.visit_block(&fn_decl.body)
+        )
+    }
+
+    fn visit_fn_arg(&mut self, fn_arg: &FnArgAst) -> String {
+        format!("{}: {}", self.text(fn_arg.name), self.visit_ty(&fn_arg.ty))
+    }
+
+    fn visit_fn_ret_ty(&mut self, fn_ret_ty: &Option<FnReturnTypeAst>) -> String {
+        match fn_ret_ty {
+            Some(fn_ret_ty) => format!(" -> {}", self.visit_ty(&fn_ret_ty.ty)),
+            None => String::new(),
+        }
+    }
+
+    fn visit_block(&mut self, block: &BlockAst) -> String {
+        let mut lines: Vec<String> = block
+            .statements
+            .iter()
+            .map(|s| self.visit_statement(s))
+            .collect();
+        lines.extend(block.return_expr.iter().map(|e| self.visit_expr(e)));
+
+        if lines.is_empty() {
+            return String::from("{}");
+        }
+
+        format!("{{\n{}\n}}", indent_lines(&lines.join("\n")))
+    }
+
+    fn visit_statement(&mut self, stmt: &StatementAst) -> String {
+        match stmt {
+            StatementAst::Semicolon(_) => String::from(";"),
+            StatementAst::LetStatement(let_stmt) => self.visit_let_statement(let_stmt),
+            StatementAst::ExpressionStatement(expr_stmt) => self.visit_expr_stmt(expr_stmt),
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_stmt: &LetStatementAst) -> String {
+        let annotation = match &let_stmt.type_annotation {
+            Some(type_annotation) => self.visit_type_annotation(type_annotation),
+            None => String::new(),
+        };
+
+        format!(
+            "let {}{} = {};",
+            self.text(let_stmt.name_ident),
+            annotation,
+            self.visit_expr(&let_stmt.value)
+        )
+    }
+
+    fn visit_type_annotation(&mut self, type_annotation: &TypeAnnotationAst) -> String {
+        format!(": {}", self.visit_ty(&type_annotation.ty))
+    }
+
+    fn visit_expr_stmt(&mut self, expr_stmt: &ExpressionStatementAst) -> String {
+        let semicolon = if expr_stmt.semicolon.is_some() {
+            ";"
+        } else {
+            ""
+        };
+        format!("{}{semicolon}", self.visit_expr(&expr_stmt.expr))
+    }
+
+    fn visit_expr(&mut self, expr: &ExpressionAst) -> String {
+        match expr {
+            ExpressionAst::Error(span) | ExpressionAst::Ident(span) => self.text(*span).to_owned(),
+            ExpressionAst::Path(path) => {
+                let segments: Vec<&str> = path.segments.iter().map(|s| self.text(*s)).collect();
+                segments.join("::")
+            }
+            ExpressionAst::Literal(literal) => literal.value.to_string(),
+            ExpressionAst::Unary(unary) => {
+                let operand = self.visit_expr(&unary.operand);
+                let operand = match &*unary.operand {
+                    ExpressionAst::Binary(_) => format!("({operand})"),
+                    _ => operand,
+                };
+
+                format!("{}{operand}", unary.op.kind.symbol())
+            }
+            ExpressionAst::Binary(binary) => {
+                let precedence = binary.op.kind.precedence();
+
+                // Operators associate to the left, so the right operand needs
+                // parentheses even at the same precedence. Comparisons can't
+                // be chained at all, and a `return` would take the operator
+                // into its value. An `if` or loop at the start would end the
+                // expression after its block.
+                let lhs_parens = match &*binary.lhs {
+                    ExpressionAst::Binary(lhs) => {
+                        let lhs_precedence = lhs.op.kind.precedence();
+                        lhs_precedence < precedence
+                            || (lhs_precedence == precedence && binary.op.kind.is_comparison())
+                    }
+                    ExpressionAst::Return(_)
+                    | ExpressionAst::If(_)
+                    | ExpressionAst::While(_)
+                    | ExpressionAst::For(_) => true,
+                    _ => false,
+                };
+                let rhs_parens = match &*binary.rhs {
+                    ExpressionAst::Binary(rhs) => rhs.op.kind.precedence() <= precedence,
+                    _ => false,
+                };
+
+                let lhs = self.visit_expr(&binary.lhs);
+                let rhs = self.visit_expr(&binary.rhs);
+                let lhs = if lhs_parens { format!("({lhs})") } else { lhs };
+                let rhs = if rhs_parens { format!("({rhs})") } else { rhs };
+
+                format!("{lhs} {} {rhs}", binary.op.kind.symbol())
+            }
+            ExpressionAst::Call(call) => {
+                let callee = self.visit_expr(&call.callee);
+                let callee = if needs_parens_before_postfix(&call.callee) {
+                    format!("({callee})")
+                } else {
+                    callee
+                };
+                let args: Vec<String> = call.args.iter().map(|arg| self.visit_expr(arg)).collect();
+
+                format!("{callee}({})", args.join(", "))
+            }
+            ExpressionAst::FieldAccess(access) => {
+                let base = self.visit_expr(&access.base);
+                let base = if needs_parens_before_postfix(&access.base) {
+                    format!("({base})")
+                } else {
+                    base
+                };
+
+                format!("{base}.{}", self.text(access.field))
+            }
+            ExpressionAst::If(if_expr) => self.visit_if_expr(if_expr),
+            ExpressionAst::While(while_expr) => format!(
+                "while {} {}",
+                self.visit_expr(&while_expr.condition),
+                self.visit_block(&while_expr.body)
+            ),
+            ExpressionAst::For(for_expr) => format!(
+                "for {} in {} {}",
+                self.text(for_expr.item_ident),
+                self.visit_expr(&for_expr.iterable),
+                self.visit_block(&for_expr.body)
+            ),
+            ExpressionAst::Return(return_expr) => match &return_expr.value {
+                Some(value) => format!("return {}", self.visit_expr(value)),
+                None => String::from("return"),
+            },
+        }
+    }
+
+    fn visit_if_expr(&mut self, if_expr: &IfExprAst) -> String {
+        let mut text = format!(
+            "if {} {}",
+            self.visit_expr(&if_expr.condition),
+            self.visit_block(&if_expr.body)
+        );
+
+        if let Some(else_branch) = &if_expr.else_branch {
+            let body = match &else_branch.body {
+                ElseBodyAst::ElseIf(if_expr) => self.visit_if_expr(if_expr),
+                ElseBodyAst::Else(block) => self.visit_block(block),
+            };
+            write!(text, " else {body}");
+        }
+
+        text
+    }
+
+    fn visit_ty(&mut self, ty: &TypeAst) -> String {
+        match ty {
+            TypeAst::Named(span) => self.text(*span).to_owned(),
+            TypeAst::Ref(ref_ty) => format!("&{}", self.visit_ty(&ref_ty.inner)),
+            TypeAst::Array(array) => format!("[{}]", self.visit_ty(&array.elem)),
+            TypeAst::Generic(generic) => {
+                let args: Vec<String> = generic.args.iter().map(|arg| self.visit_ty(arg)).collect();
+                format!("{}[{}]", self.text(generic.name_ident), args.join(", "))
+            }
+            TypeAst::FnType(fn_ty) => {
+                let params: Vec<String> = fn_ty
+                    .params
+                    .iter()
+                    .map(|param| self.visit_ty(param))
+                    .collect();
+                let mut text = format!("fn({})", params.join(", "));
+                if let Some(ret) = &fn_ty.ret {
+                    write!(text, " -> {}", self.visit_ty(ret));
+                }
+                text
+            }
+        }
+    }
+}
+
+/// Serialize `file` as JSON, for tools that want to read the AST. Every node
+/// is an object with a `kind`, and identifiers and other tokens come with the
+/// location of their `span`.
+pub fn to_json(file: &FileAst, source: &Source) -> String {
+    JsonPrintAst { source }.visit_file(file)
+}
+
+/// Prints each node as a JSON value.
+pub struct JsonPrintAst<'a> {
+    /// The source the AST was parsed from.
+    source: &'a Source,
+}
+
+impl<'a> JsonPrintAst<'a> {
+    /// A JSON object with the given fields, whose values are already JSON.
+    fn object(kind: &str, fields: Vec<(&str, String)>) -> String {
+        let mut out = String::from("{\"kind\":");
+        write_string(&mut out, kind);
+
+        for (name, value) in fields {
+            out.push(',');
+            write_string(&mut out, name);
+            out.push(':');
+            out.push_str(&value);
+        }
+
+        out.push('}');
+        out
+    }
+
+    /// A JSON array of values that are already JSON.
+    fn list(values: Vec<String>) -> String {
+        format!("[{}]", values.join(","))
+    }
+
+    /// A JSON string with the contents `text`.
+    fn string(text: &str) -> String {
+        let mut out = String::new();
+        write_string(&mut out, text);
+        out
+    }
+
+    /// The location of `span`, with 1-indexed lines and columns where the end
+    /// is exclusive.
+    fn span(&self, span: Span) -> String {
+        let (start, end) = (span.start(), span.end());
+
+        format!(
+            "{{\"file\":{},\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}",
+            Self::string(self.source.filename()),
+            self.source.line_of(start),
+            self.source.col_of(start),
+            self.source.line_of(end),
+            self.source.col_of(end),
+        )
+    }
+
+    /// An identifier or other token, as its text and location.
+    fn token(&self, span: Span) -> String {
+        format!(
+            "{{\"text\":{},\"span\":{}}}",
+            Self::string(self.source.text_of_span(span)),
+            self.span(span)
+        )
+    }
+}
+
+impl<'a> AstVisitor<String> for JsonPrintAst<'a> {
+    fn visit_file(&mut self, file: &FileAst) -> String {
+        let decls: Vec<String> = file
+            .declarations
+            .iter()
+            .map(|d| self.visit_decl(d))
+            .collect();
+
+        Self::object(
+            "File",
+            vec![
+                ("filename", Self::string(self.source.filename())),
+                ("declarations", Self::list(decls)),
+            ],
+        )
+    }
+
+    fn visit_decl(&mut self, decl: &DeclarationAst) -> String {
+        match decl {
+            DeclarationAst::FnDecl(fn_decl) => self.visit_fn_decl(fn_decl),
+        }
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &FnDeclAst) -> String {
+        let args: Vec<String> = fn_decl.args.iter().map(|a| self.visit_fn_arg(a)).collect();
+
+        Self::object(
+            "FnDecl",
+            vec![
+                ("name", self.token(fn_decl.name_ident)),
+                ("args", Self::list(args)),
+                ("return_ty", self.visit_fn_ret_ty(&fn_decl.return_ty)),
+                ("body", self.visit_block(&fn_decl.body)),
+            ],
+        )
+    }
+
+    fn visit_fn_arg(&mut self, fn_arg: &FnArgAst) -> String {
+        Self::object(
+            "FnArg",
+            vec![
+                ("name", self.token(fn_arg.name)),
+                ("ty", self.visit_ty(&fn_arg.ty)),
+            ],
+        )
+    }
+
+    fn visit_fn_ret_ty(&mut self, fn_ret_ty: &Option<FnReturnTypeAst>) -> String {
+        match fn_ret_ty {
+            Some(fn_ret_ty) => self.visit_ty(&fn_ret_ty.ty),
+            None => String::from("null"),
+        }
+    }
+
+    fn visit_block(&mut self, block: &BlockAst) -> String {
+        let statements: Vec<String> = block
+            .statements
+            .iter()
+            .map(|s| self.visit_statement(s))
+            .collect();
+        let return_expr = match &block.return_expr {
+            Some(expr) => self.visit_expr(expr),
+            None => String::from("null"),
+        };
+
+        Self::object(
+            "Block",
+            vec![
+                ("statements", Self::list(statements)),
+                ("return_expr", return_expr),
+            ],
+        )
+    }
+
+    fn visit_statement(&mut self, stmt: &StatementAst) -> String {
+        match stmt {
+            StatementAst::Semicolon(span) => {
+                Self::object("Semicolon", vec![("span", self.span(*span))])
+            }
+            StatementAst::LetStatement(let_stmt) => self.visit_let_statement(let_stmt),
+            StatementAst::ExpressionStatement(expr_stmt) => self.visit_expr_stmt(expr_stmt),
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_stmt: &LetStatementAst) -> String {
+        let ty = match &let_stmt.type_annotation {
+            Some(type_annotation) => self.visit_type_annotation(type_annotation),
+            None => String::from("null"),
+        };
+
+        Self::object(
+            "Let",
+            vec![
+                ("name", self.token(let_stmt.name_ident)),
+                ("ty", ty),
+                ("value", self.visit_expr(&let_stmt.value)),
+            ],
+        )
+    }
+
+    fn visit_type_annotation(&mut self, type_annotation: &TypeAnnotationAst) -> String {
+        self.visit_ty(&type_annotation.ty)
+    }
+
+    fn visit_expr_stmt(&mut self, expr_stmt: &ExpressionStatementAst) -> String {
+        Self::object(
+            "ExprStmt",
+            vec![
+                ("expr", self.visit_expr(&expr_stmt.expr)),
+                ("semicolon", expr_stmt.semicolon.is_some().to_string()),
+            ],
+        )
+    }
+
+    fn visit_expr(&mut self, expr: &ExpressionAst) -> String {
+        match expr {
+            ExpressionAst::Error(span) => Self::object("Error", vec![("span", self.span(*span))]),
+            ExpressionAst::Ident(span) => Self::object("Ident", vec![("name", self.token(*span))]),
+            ExpressionAst::Path(path) => {
+                let segments: Vec<String> = path.segments.iter().map(|s| self.token(*s)).collect();
+                Self::object("Path", vec![("segments", Self::list(segments))])
+            }
+            ExpressionAst::Literal(literal) => Self::object(
+                "Literal",
+                vec![
+                    ("value", literal.value.to_string()),
+                    ("span", self.span(literal.span)),
+                ],
+            ),
+            ExpressionAst::Unary(unary) => Self::object(
+                "Unary",
+                vec![
+                    ("op", Self::string(unary.op.kind.symbol())),
+                    ("op_span", self.span(unary.op.span)),
+                    ("operand", self.visit_expr(&unary.operand)),
+                ],
+            ),
+            ExpressionAst::Binary(binary) => Self::object(
+                "Binary",
+                vec![
+                    ("op", Self::string(binary.op.kind.symbol())),
+                    ("op_span", self.span(binary.op.span)),
+                    ("lhs", self.visit_expr(&binary.lhs)),
+                    ("rhs", self.visit_expr(&binary.rhs)),
+                ],
+            ),
+            ExpressionAst::Call(call) => {
+                let args: Vec<String> = call.args.iter().map(|arg| self.visit_expr(arg)).collect();
+                Self::object(
+                    "Call",
+                    vec![
+                        ("callee", self.visit_expr(&call.callee)),
+                        ("args", Self::list(args)),
+                    ],
+                )
+            }
+            ExpressionAst::FieldAccess(access) => Self::object(
+                "FieldAccess",
+                vec![
+                    ("base", self.visit_expr(&access.base)),
+                    ("field", self.token(access.field)),
+                ],
+            ),
+            ExpressionAst::If(if_expr) => self.visit_if_expr(if_expr),
+            ExpressionAst::While(while_expr) => Self::object(
+                "While",
+                vec![
+                    ("condition", self.visit_expr(&while_expr.condition)),
+                    ("body", self.visit_block(&while_expr.body)),
+                ],
+            ),
+            ExpressionAst::For(for_expr) => Self::object(
+                "For",
+                vec![
+                    ("item", self.token(for_expr.item_ident)),
+                    ("iterable", self.visit_expr(&for_expr.iterable)),
+                    ("body", self.visit_block(&for_expr.body)),
+                ],
+            ),
+            ExpressionAst::Return(return_expr) => {
+                let value = match &return_expr.value {
+                    Some(value) => self.visit_expr(value),
+                    None => String::from("null"),
+                };
+                Self::object("Return", vec![("value", value)])
+            }
+        }
+    }
+
+    fn visit_if_expr(&mut self, if_expr: &IfExprAst) -> String {
+        let else_branch = match &if_expr.else_branch {
+            Some(else_branch) => match &else_branch.body {
+                ElseBodyAst::ElseIf(if_expr) => self.visit_if_expr(if_expr),
+                ElseBodyAst::Else(block) => self.visit_block(block),
+            },
+            None => String::from("null"),
+        };
+
+        Self::object(
+            "If",
+            vec![
+                ("condition", self.visit_expr(&if_expr.condition)),
+                ("body", self.visit_block(&if_expr.body)),
+                ("else", else_branch),
+            ],
+        )
+    }
+
+    fn visit_ty(&mut self, ty: &TypeAst) -> String {
+        match ty {
+            TypeAst::Named(span) => Self::object("Type", vec![("name", self.token(*span))]),
+            TypeAst::Ref(ref_ty) => {
+                Self::object("RefType", vec![("inner", self.visit_ty(&ref_ty.inner))])
+            }
+            TypeAst::Array(array) => {
+                Self::object("ArrayType", vec![("elem", self.visit_ty(&array.elem))])
+            }
+            TypeAst::Generic(generic) => {
+                let args: Vec<String> = generic.args.iter().map(|arg| self.visit_ty(arg)).collect();
+                Self::object(
+                    "GenericType",
+                    vec![
+                        ("name", self.token(generic.name_ident)),
+                        ("args", Self::list(args)),
+                    ],
+                )
+            }
+            TypeAst::FnType(fn_ty) => {
+                let params: Vec<String> = fn_ty
+                    .params
+                    .iter()
+                    .map(|param| self.visit_ty(param))
+                    .collect();
+                let ret = match &fn_ty.ret {
+                    Some(ret) => self.visit_ty(ret),
+                    None => String::from("null"),
+                };
+
+                Self::object("FnType", vec![("params", Self::list(params)), ("ret", ret)])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::{parse_source, MAX_TREE_DEPTH},
+        source_map::SourceMap,
+    };
+    use std::thread;
+
+    /// Parse `text`, which must not have errors, and print it with
+    /// `pretty_print`.
+    fn printed(text: &str) -> String {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+        let source = source_map.get_source(id);
+        let file = parse_source(source).unwrap();
+
+        let mut out = String::new();
+        pretty_print(&file, source, &mut out);
+        out
+    }
+
+    /// Parse `text` as the body of a function, which must not have errors,
+    /// and print only the expression it ends in.
+    fn printed_expr(text: &str) -> String {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), format!("fn f() {{ {text} }}"));
+        let source = source_map.get_source(id);
+        let file = parse_source(source).unwrap();
+
+        let DeclarationAst::FnDecl(fn_decl) = &file.declarations[0];
+        PrettyPrintAst::new(source).visit_expr(fn_decl.body.return_expr.as_ref().unwrap())
+    }
+
+    #[test]
+    fn iterative_matches_recursive() {
+        let text = "
+            fn f(a: i32, b: &[i32]) -> i32 {
+                let x: i32 = -(a + b.len) * g(1, (2 - 3) / 4);
+                if x < 0 { return -x; } else if x == 0 { 1 } else { h(x).y }
+            }
+        ";
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text.to_owned());
+        let source = source_map.get_source(id);
+        let file = parse_source(source).unwrap();
+
+        let mut recursive = String::new();
+        pretty_print(&file, source, &mut recursive);
+        let mut iterative = String::new();
+        pretty_print_iterative(&file, source, &mut iterative);
+
+        assert_eq!(iterative, recursive);
+    }
+
+    #[test]
+    fn source_printer_minimal_parens() {
+        let cases = [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + b * c", "a + b * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("((a + b)) * c", "(a + b) * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("-(a + b) * -c", "-(a + b) * -c"),
+            ("(a || b) && !(c == d)", "(a || b) && !(c == d)"),
+            ("f((a + b) * c, (d)).e", "f((a + b) * c, d).e"),
+            ("(-a).b", "(-a).b"),
+            ("(-f)(x)", "(-f)(x)"),
+            ("-a.b", "-a.b"),
+            (
+                "(if c { a } else { b }) + 1",
+                "(if c {\n    a\n} else {\n    b\n}) + 1",
+            ),
+            (
+                "(if c { a } else { b }).d",
+                "(if c {\n    a\n} else {\n    b\n}).d",
+            ),
+            ("(while c {})(x)", "(while c {})(x)"),
+            ("(for x in y {}).z", "(for x in y {}).z"),
+        ];
+
+        for (text, expected) in cases {
+            let body = format!("fn f() {{ {text} }}");
+            let mut source_map = SourceMap::new();
+            let id = source_map.add_source(String::from("test.fern"), body);
+            let source = source_map.get_source(id);
+            let file = parse_source(source).unwrap();
+            let DeclarationAst::FnDecl(fn_decl) = &file.declarations[0];
+
+            let printed = ExprSourcePrinter::new(source)
+                .visit_expr(fn_decl.body.return_expr.as_ref().unwrap());
+            assert_eq!(printed, expected, "printing `{text}`");
+
+            // The printed code means the same thing as the original.
+            assert_eq!(
+                printed_expr(&printed),
+                printed_expr(text),
+                "re-parsing `{printed}`"
+            );
+        }
+    }
+
+    #[test]
+    fn pretty_prints_block() {
+        let expected = "\
+File {
+  filename: test.fern
+  declarations: [
+    FnDecl {
+      name: f
+      args: []
+      ret_ty: ()
+      body: Block {
+        statements: [
+          ExprStmt {
+            expr: Call {
+              callee: g
+              args: []
+            }
+            semicolon: true
+          }
+        ]
+        return_expr: x
+      }
+    }
+  ]
+}";
+        assert_eq!(printed("fn f() { g(); x }"), expected);
+    }
+
+    #[test]
+    fn pretty_prints_statements() {
+        let expected = "\
+File {
+  filename: test.fern
+  declarations: [
+    FnDecl {
+      name: f
+      args: []
+      ret_ty: ()
+      body: Block {
+        statements: [
+          Semicolon
+          Let {
+            name: x
+            ty: int
+            value: 1
+          }
+          Let {
+            name: y
+            ty: None
+            value: x
+          }
+        ]
+        return_expr: None
+      }
+    }
+  ]
+}";
+        assert_eq!(printed("fn f() { ; let x: int = 1; let y = x; }"), expected);
+    }
+
+    #[test]
+    fn pretty_prints_expressions() {
+        let expected = "\
+Binary {
+  op: +
+  lhs: 1
+  rhs: Binary {
+    op: *
+    lhs: 2
+    rhs: 3
+  }
+}";
+        assert_eq!(printed_expr("1 + 2 * 3"), expected);
+
+        let expected = "\
+Call {
+  callee: f
+  args: [
+    a
+    Unary {
+      op: -
+      operand: FieldAccess {
+        base: b
+        field: c
+      }
+    }
+  ]
+}";
+        assert_eq!(printed_expr("f(a, -b.c)"), expected);
+    }
+
+    #[test]
+    fn to_json_includes_names_and_types() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(
+            String::from("test.fern"),
+            String::from("fn f(a: int, b: &[bool]) {}"),
+        );
+        let source = source_map.get_source(id);
+        let json = to_json(&parse_source(source).unwrap(), source);
+
+        assert!(json.starts_with(r#"{"kind":"File","filename":"test.fern","declarations":[{"kind":"FnDecl","name":{"text":"f","#));
+        assert!(json.contains(r#""name":{"text":"a","span":{"file":"test.fern","line":1,"col":6,"end_line":1,"end_col":7}}"#));
+        assert!(json.contains(r#""ty":{"kind":"Type","name":{"text":"int","#));
+        assert!(json.contains(r#""ty":{"kind":"RefType","inner":{"kind":"ArrayType","elem":{"kind":"Type","name":{"text":"bool","#));
+    }
+
+    #[test]
+    fn pretty_prints_spans() {
+        let mut source_map = SourceMap::new();
+        let text = String::from("fn f(a: int) {\n    a\n}");
+        let id = source_map.add_source(String::from("test.fern"), text);
+        let source = source_map.get_source(id);
+        let file = parse_source(source).unwrap();
+
+        let mut out = String::new();
+        pretty_print_with_spans(&file, source, &mut out);
+        let expected = "\
+File {
+  filename: test.fern
+  declarations: [
+    FnDecl {
+      span: 1:1-3:2
+      name: f
+      args: [
+        FnArg {
+          span: 1:6-1:12
+          name: a
+          ty: int @ 1:9-1:12
+        }
+      ]
+      ret_ty: ()
+      body: Block {
+        span: 1:14-3:2
+        statements: []
+        return_expr: a @ 2:5-2:6
+      }
+    }
+  ]
+}";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn mutable_visitor_renames_idents() {
+        /// Points every ident expression at `to` instead.
+        struct Rename {
+            /// The span of the new name.
+            to: Span,
+        }
+
+        impl AstVisitorMut for Rename {
+            fn visit_expr(&mut self, expr: &mut ExpressionAst) {
+                match expr {
+                    ExpressionAst::Ident(span) => *span = self.to,
+                    _ => walk_expr(self, expr),
+                }
+            }
+        }
+
+        let mut source_map = SourceMap::new();
+        let text = String::from("fn f(x: int, y: int) { g(x) + -x }");
+        let id = source_map.add_source(String::from("test.fern"), text);
+        let source = source_map.get_source(id);
+        let mut file = parse_source(source).unwrap();
+
+        let to = source.span(13, 14);
+        Rename { to }.visit_file(&mut file);
+
+        let DeclarationAst::FnDecl(fn_decl) = &file.declarations[0];
+        let expr = fn_decl.body.return_expr.as_ref().unwrap();
+        assert_eq!(ExprSourcePrinter::new(source).visit_expr(expr), "y(y) + -y");
+    }
+
+    #[test]
+    fn iterative_handles_deep_expressions() {
+        let n = MAX_TREE_DEPTH - 8;
+        let text = format!("fn f() {{ {} }}", vec!["1"; n].join(" + "));
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text);
+        let source = source_map.get_source(id);
+        let file = parse_source(source).unwrap();
+
+        // The stack is far too small to recurse once per level of the
+        // expression.
+        let printed = thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(64 * 1024)
+                .spawn_scoped(scope, || {
+                    let mut out = String::new();
+                    pretty_print_iterative(&file, source, &mut out);
+                    out
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+
+        let ops = printed.lines().filter(|line| line.trim() == "op: +");
+        assert_eq!(ops.count(), n - 1);
+        assert!(printed.ends_with("\n        }\n      }\n    }\n  ]\n}"));
     }
 }
@@ -0,0 +1,181 @@
+//! Folds operations whose operands are all literals into a single literal.
+
+use crate::{
+    diagnostics::{specifics, Diagnostics},
+    parse::{
+        ast::{BinaryExprAst, BinaryOp, ExpressionAst, FileAst, LiteralAst, LiteralValue, UnaryOp},
+        visit::{walk_expr, AstVisitorMut},
+    },
+    source_map::Span,
+};
+
+/// Fold integer arithmetic and boolean logic on literals within `expr`, so
+/// `2 + 3` becomes `5`. Anything that isn't entirely made of literals is left
+/// untouched, as is a division by zero, which is reported as a warning.
+pub fn const_fold(expr: &mut ExpressionAst, diags: &mut Diagnostics) {
+    ConstFolder { diags }.visit_expr(expr);
+}
+
+/// Fold every expression in `file` like `const_fold` does.
+pub fn const_fold_file(file: &mut FileAst, diags: &mut Diagnostics) {
+    ConstFolder { diags }.visit_file(file);
+}
+
+/// The visitor doing the folding, which folds each expression after its
+/// operands.
+struct ConstFolder<'a> {
+    /// Where division by zero is reported.
+    diags: &'a mut Diagnostics,
+}
+
+impl AstVisitorMut for ConstFolder<'_> {
+    fn visit_expr(&mut self, expr: &mut ExpressionAst) {
+        walk_expr(self, expr);
+
+        let folded = match expr {
+            ExpressionAst::Unary(unary) => fold_unary(unary.op.kind, unary.op.span, &unary.operand),
+            ExpressionAst::Binary(binary) => fold_binary(binary, self.diags),
+            _ => None,
+        };
+
+        if let Some(literal) = folded {
+            *expr = ExpressionAst::Literal(literal);
+        }
+    }
+}
+
+/// The literal applying the unary operator `op` at `op_span` to `operand`
+/// evaluates to, if `operand` is a literal it can be applied to.
+fn fold_unary(op: UnaryOp, op_span: Span, operand: &ExpressionAst) -> Option<LiteralAst> {
+    // Integers are unsigned, so only boolean negation folds.
+    let (UnaryOp::Not, ExpressionAst::Literal(operand)) = (op, operand) else {
+        return None;
+    };
+    let LiteralValue::Bool(value) = operand.value else {
+        return None;
+    };
+
+    Some(LiteralAst {
+        span: Span::union(op_span, operand.span),
+        value: LiteralValue::Bool(!value),
+    })
+}
+
+/// The literal `binary` evaluates to, if both of its operands are literals
+/// and the operation is well defined for them. Overflow isn't folded so it
+/// can be reported by a later stage.
+fn fold_binary(binary: &BinaryExprAst, diags: &mut Diagnostics) -> Option<LiteralAst> {
+    use BinaryOp as B;
+    use LiteralValue::{Bool, Int};
+
+    let (ExpressionAst::Literal(lhs), ExpressionAst::Literal(rhs)) = (&*binary.lhs, &*binary.rhs)
+    else {
+        return None;
+    };
+
+    let value = match (lhs.value, binary.op.kind, rhs.value) {
+        (Int(_), B::Div | B::Rem, Int(0)) => {
+            diags.push(specifics::passes::division_by_zero(rhs.span));
+            return None;
+        }
+
+        (Int(a), B::Add, Int(b)) => Int(a.checked_add(b)?),
+        (Int(a), B::Sub, Int(b)) => Int(a.checked_sub(b)?),
+        (Int(a), B::Mul, Int(b)) => Int(a.checked_mul(b)?),
+        (Int(a), B::Div, Int(b)) => Int(a / b),
+        (Int(a), B::Rem, Int(b)) => Int(a % b),
+        (Int(a), B::BitAnd, Int(b)) => Int(a & b),
+        (Int(a), B::BitOr, Int(b)) => Int(a | b),
+        (Int(a), B::BitXor, Int(b)) => Int(a ^ b),
+        (Int(a), B::Shl, Int(b)) => Int(a.checked_shl(b.try_into().ok()?)?),
+        (Int(a), B::Shr, Int(b)) => Int(a.checked_shr(b.try_into().ok()?)?),
+        (Int(a), B::Lt, Int(b)) => Bool(a < b),
+        (Int(a), B::Lte, Int(b)) => Bool(a <= b),
+        (Int(a), B::Gt, Int(b)) => Bool(a > b),
+        (Int(a), B::Gte, Int(b)) => Bool(a >= b),
+
+        (Bool(a), B::And | B::BitAnd, Bool(b)) => Bool(a && b),
+        (Bool(a), B::Or | B::BitOr, Bool(b)) => Bool(a || b),
+        (Bool(a), B::BitXor, Bool(b)) => Bool(a ^ b),
+
+        (a, B::Eq, b) if same_kind(a, b) => Bool(a == b),
+        (a, B::NotEq, b) if same_kind(a, b) => Bool(a != b),
+
+        _ => return None,
+    };
+
+    Some(LiteralAst {
+        span: Span::union(lhs.span, rhs.span),
+        value,
+    })
+}
+
+/// Whether `a` and `b` are literals of the same type.
+fn same_kind(a: LiteralValue, b: LiteralValue) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::{ast::DeclarationAst, parse_source},
+        source_map::SourceMap,
+    };
+
+    /// Parse `expr` as the value of a function and fold it.
+    fn fold(expr: &str) -> (ExpressionAst, Diagnostics) {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), format!("fn f() {{ {expr} }}"));
+
+        let Ok(mut file) = parse_source(source_map.get_source(id)) else {
+            panic!("`{expr}` doesn't parse");
+        };
+        let DeclarationAst::FnDecl(fn_decl) = file.declarations.remove(0);
+        let mut expr = fn_decl.body.return_expr.unwrap();
+
+        let mut diags = Diagnostics::new();
+        const_fold(&mut expr, &mut diags);
+        (expr, diags)
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        let (expr, diags) = fold("1 + 2 * 3");
+
+        assert!(diags.is_empty());
+        let ExpressionAst::Literal(literal) = expr else {
+            panic!("`1 + 2 * 3` wasn't folded");
+        };
+        assert_eq!(literal.value, LiteralValue::Int(7));
+    }
+
+    #[test]
+    fn leaves_variables_alone() {
+        let (expr, diags) = fold("a + 1");
+
+        assert!(diags.is_empty());
+        assert!(matches!(expr, ExpressionAst::Binary(_)));
+    }
+
+    #[test]
+    fn leaves_division_by_zero_and_warns() {
+        for text in ["1 / 0", "4 % 0"] {
+            let (expr, diags) = fold(text);
+
+            assert_eq!(diags.warning_count(), 1, "folding `{text}`");
+            assert!(matches!(expr, ExpressionAst::Binary(_)), "folding `{text}`");
+        }
+    }
+
+    #[test]
+    fn folds_boolean_logic() {
+        let (expr, diags) = fold("true && !false");
+
+        assert!(diags.is_empty());
+        let ExpressionAst::Literal(literal) = expr else {
+            panic!("`true && !false` wasn't folded");
+        };
+        assert_eq!(literal.value, LiteralValue::Bool(true));
+    }
+}
@@ -0,0 +1,3 @@
+//! Transformations over the AST that run after parsing.
+
+pub mod const_fold;
@@ -1,4 +1,14 @@
-use std::{fs::read_to_string, ops::Range};
+use crate::utils::consistency_assert;
+use std::{
+    cmp,
+    fs::{read_dir, read_to_string},
+    io, iter,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// The name given to a `Source` read from the standard input.
+pub const STDIN_NAME: &str = "<stdin>";
 
 /// A struct to manage and own all the `Source`s the compiler uses.
 #[derive(Debug, Default)]
@@ -6,6 +16,10 @@ pub struct SourceMap {
     /// The list of `Source`s. The index in this vector is the `SourceId` of a
     /// `Source`.
     sources: Vec<Source>,
+
+    /// Whether a lone '\r' ends a line in the `Source`s we add, as it does in
+    /// files with old Mac style line endings.
+    bare_cr_newlines: bool,
 }
 
 impl SourceMap {
@@ -14,12 +28,35 @@ impl SourceMap {
         Default::default()
     }
 
+    /// Set whether a lone '\r' is treated as the end of a line, in addition
+    /// to '\n' and "\r\n", for all sources added after this. This is off by
+    /// default.
+    pub fn with_bare_cr_newlines(mut self, enabled: bool) -> Self {
+        self.bare_cr_newlines = enabled;
+        self
+    }
+
     /// Creates a new `Source` from the name `filename` and content `text`.
     ///
     /// Returns the `SourceId` of the newly created `Source`.
     pub fn add_source(&mut self, filename: String, text: String) -> SourceId {
+        self.add_source_with_line_offset(filename, text, 0)
+    }
+
+    /// Creates a new `Source` for a snippet that was taken from a larger
+    /// document, where `line_offset` lines preceded it. Line numbers reported
+    /// for this source are those of the original document.
+    ///
+    /// Returns the `SourceId` of the newly created `Source`.
+    pub fn add_source_with_line_offset(
+        &mut self,
+        filename: String,
+        text: String,
+        line_offset: usize,
+    ) -> SourceId {
         let id = SourceId(self.sources.len());
-        let source = Source::new(id, filename, text);
+        let source =
+            Source::new(id, filename, text, self.bare_cr_newlines).with_line_offset(line_offset);
         self.sources.push(source);
 
         id
@@ -27,10 +64,94 @@ impl SourceMap {
 
     /// Load a `Source` from the file with the given name.
     ///
-    /// Panics if the file doesn't exist.
-    pub fn add_source_from_file(&mut self, filename: &str) -> SourceId {
-        let text = read_to_string(filename).unwrap();
-        self.add_source(filename.to_owned(), text)
+    /// Returns an error if the file can't be read.
+    pub fn add_source_from_file(&mut self, filename: &str) -> io::Result<SourceId> {
+        let text = read_to_string(filename)?;
+        let id = self.add_source(filename.to_owned(), text);
+        self.sources[id.0].path = std::fs::canonicalize(filename).ok();
+
+        Ok(id)
+    }
+
+    /// Load a `Source` from the file with the given name like
+    /// `add_source_from_file`, unless a source with that name was already
+    /// added, in which case its id is returned instead.
+    ///
+    /// Returns an error if the file has to be read and can't be.
+    pub fn get_or_add_from_file(&mut self, filename: &str) -> io::Result<SourceId> {
+        match self.sources.iter().find(|s| s.filename() == filename) {
+            Some(source) => Ok(source.id()),
+            None => self.add_source_from_file(filename),
+        }
+    }
+
+    /// Load a `Source` named `<stdin>` from everything on the standard input.
+    ///
+    /// Returns an error if stdin can't be read, for example because it isn't
+    /// valid UTF-8.
+    pub fn add_source_from_stdin(&mut self) -> io::Result<SourceId> {
+        let text = io::read_to_string(io::stdin())?;
+        Ok(self.add_source(STDIN_NAME.to_owned(), text))
+    }
+
+    /// Load every file with the extension `ext` (e.g. `"fern"`) in `dir` and
+    /// its subdirectories, in order of their paths.
+    ///
+    /// Returns an error if a directory can't be read. Otherwise there is a
+    /// result for each file found, which is an error if that particular file
+    /// can't be read, for example because it isn't valid UTF-8.
+    pub fn add_sources_from_dir(
+        &mut self,
+        dir: &str,
+        ext: &str,
+    ) -> io::Result<Vec<io::Result<SourceId>>> {
+        let ext = ext.trim_start_matches('.');
+
+        let mut paths = Vec::new();
+        let mut dirs = vec![PathBuf::from(dir)];
+        while let Some(dir) = dirs.pop() {
+            for entry in read_dir(dir)? {
+                let entry = entry?;
+                let path = entry.path();
+
+                // Unlike `Path::is_dir`, this doesn't follow symlinks, so a
+                // link to a parent directory can't make us loop forever.
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|e| e == ext) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+
+        let ids = paths
+            .into_iter()
+            .map(|path| {
+                let text = read_to_string(&path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("{}: {e}", path.display()))
+                })?;
+                let id = self.add_source(path.display().to_string(), text);
+                self.sources[id.0].path = std::fs::canonicalize(&path).ok();
+
+                Ok(id)
+            })
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// Replace the text of the `Source` with the given id, such as after it
+    /// was edited, keeping its id, name, path, and line offset.
+    ///
+    /// Any `Span` or `SourcePos` into the old text is invalidated, since it
+    /// refers to bytes that may no longer exist or mean something else.
+    pub fn replace_source(&mut self, id: SourceId, text: String) {
+        let old = &self.sources[id.0];
+        let mut source = Source::new(id, old.filename.clone(), text, self.bare_cr_newlines)
+            .with_line_offset(old.line_offset);
+        source.path = old.path.clone();
+        self.sources[id.0] = source;
     }
 
     /// Returns the `Source` with the given id.
@@ -54,20 +175,37 @@ pub struct Source {
     /// be a file name if the code does not come from disk.
     filename: String,
 
+    /// The absolute path of the file this source was loaded from, resolved
+    /// once when it was read. `None` if it didn't come from disk.
+    path: Option<PathBuf>,
+
     /// The text content of the source file.
     text: String,
 
-    /// The cached indices of all '\n' characters in the `text`. This is used
-    /// to efficiently compute line numbers.
+    /// The cached indices of all the characters ending a line in the `text`.
+    /// This is used to efficiently compute line numbers.
     newlines: Vec<usize>,
+
+    /// The number of lines that came before this source in the document it
+    /// was taken from. This is added to every line number we report.
+    line_offset: usize,
 }
 
 impl Source {
-    fn compute_newlines(text: &str) -> Vec<usize> {
-        let mut newlines: Vec<usize> = text.match_indices('\n').map(|(i, _)| i).collect();
+    /// Find the index of each character that ends a line. If `bare_cr` is set
+    /// then a '\r' that isn't followed by a '\n' also ends a line.
+    fn compute_newlines(text: &str, bare_cr: bool) -> Vec<usize> {
+        let bytes = text.as_bytes();
+        let is_newline = |i: usize| match bytes[i] {
+            b'\n' => true,
+            b'\r' => bare_cr && bytes.get(i + 1) != Some(&b'\n'),
+            _ => false,
+        };
+
+        let mut newlines: Vec<usize> = (0..bytes.len()).filter(|&i| is_newline(i)).collect();
 
         // We make sure the last line is terminated.
-        if text.chars().next_back() != Some('\n') {
+        if newlines.last().map(|&i| i + 1) != Some(text.len()) {
             newlines.push(text.len());
         }
 
@@ -75,16 +213,25 @@ impl Source {
     }
 
     /// Create a new source file with the given id from our `SourceMap`,
-    /// `filename`, and `text` content.
-    fn new(id: SourceId, filename: String, text: String) -> Self {
+    /// `filename`, and `text` content. `bare_cr` is whether a lone '\r' ends
+    /// a line.
+    fn new(id: SourceId, filename: String, text: String, bare_cr: bool) -> Self {
         Self {
             id,
             filename,
-            newlines: Self::compute_newlines(&text),
+            path: None,
+            newlines: Self::compute_newlines(&text, bare_cr),
             text,
+            line_offset: 0,
         }
     }
 
+    /// Make the reported line numbers start at `line_offset + 1`.
+    fn with_line_offset(mut self, line_offset: usize) -> Self {
+        self.line_offset = line_offset;
+        self
+    }
+
     /// Get the id this source has in its `SourceMap`.
     pub fn id(&self) -> SourceId {
         self.id
@@ -96,13 +243,21 @@ impl Source {
         &self.filename
     }
 
+    /// Get the absolute path of the file this source was loaded from, if it
+    /// was loaded from disk.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     /// Get the text content of the source file.
     pub fn text(&self) -> &str {
         &self.text
     }
 
+    /// Get the text within `span`. This is empty if the span doesn't fit in
+    /// the text.
     pub(crate) fn text_of_span(&self, span: Span) -> &str {
-        &self.text[span.byte_range()]
+        self.text.get(span.byte_range()).unwrap_or_default()
     }
 
     /// Get the `SourcePos` for the given byte offset. `byte` should we aligned
@@ -110,45 +265,100 @@ impl Source {
     ///
     /// This should be the only way to create a `SourcePos`.
     fn pos_from_byte(&self, byte: usize) -> SourcePos {
-        assert!(self.text().is_char_boundary(byte));
+        consistency_assert!(self.text().is_char_boundary(byte));
         SourcePos::new(self.id(), byte)
     }
 
+    /// Get the `SourcePos` for the byte offset `byte`, for tools that only
+    /// have plain offsets. The end of the text is a valid position.
+    ///
+    /// Returns `None` if `byte` is past the end of the text or in the middle
+    /// of a character.
+    pub fn pos_at(&self, byte: usize) -> Option<SourcePos> {
+        self.text
+            .is_char_boundary(byte)
+            .then(|| self.pos_from_byte(byte))
+    }
+
     /// The 1-indexed line number of the given position within this source.
     ///
     /// The newline for a line, if it exists, is considered part of the line
     /// it ends.
     pub fn line_of(&self, pos: SourcePos) -> usize {
-        assert!(pos.src_id() == self.id());
+        consistency_assert!(pos.src_id() == self.id());
 
-        match self.newlines.binary_search(&pos.byte()) {
+        let line = match self.newlines.binary_search(&pos.byte()) {
             // This is exactly a newline which is the last character on that line
             Ok(i) => i + 1,
             // This is between newlines in which case we want the index before
             // the newline which is luckily what `binary_search` gives.
             Err(i) => i + 1,
-        }
+        };
+
+        line + self.line_offset
+    }
+
+    /// The line number of the last character in `span`, or of its start if it
+    /// is empty. A span ending with a newline therefore ends on the line that
+    /// newline ends.
+    pub fn end_line_of(&self, span: Span) -> usize {
+        consistency_assert!(span.src_id() == self.id());
+
+        // Only the byte is used to find the line, so this doesn't need to be
+        // on a char boundary.
+        let last = span.end().byte().saturating_sub(1).max(span.start().byte());
+        self.line_of(SourcePos::new(self.id(), last))
     }
 
-    /// The first byte of the 1-indexed line.
-    fn first_byte_of_line(&self, line: usize) -> usize {
-        let line = line - 1;
-        if line == 0 {
-            0 // The first line starts at byte 0.
+    /// The first byte of the 1-indexed line, as reported by `line_of`. Lines
+    /// past the end of the text start at the end of the text, while lines
+    /// before the line offset aren't in this source at all.
+    fn first_byte_of_line(&self, line: usize) -> Option<usize> {
+        let index = line.checked_sub(self.line_offset + 1)?;
+        if index == 0 {
+            Some(0) // The first line starts at byte 0.
         } else {
             // Other lines start at one past the end of the previous line.
-            self.newlines[line - 1] + 1
+            let start = self.newlines.get(index - 1).map_or(usize::MAX, |&i| i + 1);
+            Some(start.min(self.text.len()))
         }
     }
 
     /// The 1-indexed column number of the given position within this source.
+    /// Columns count characters rather than bytes, so a multibyte character
+    /// only takes up one column. A "\r\n" is a single column like "\n".
     pub fn col_of(&self, pos: SourcePos) -> usize {
-        assert!(pos.src_id() == self.id());
+        consistency_assert!(pos.src_id() == self.id());
 
-        let line = self.line_of(pos);
-        let start_byte = self.first_byte_of_line(line);
+        let line_span = self.span_of_line(self.line_of(pos));
+        let start_byte = line_span.start().byte();
+        let byte = pos.byte().min(line_span.end().byte());
 
-        pos.byte() - start_byte + 1
+        let chars_before = self.text[start_byte..]
+            .char_indices()
+            .take_while(|&(i, _)| start_byte + i < byte)
+            .count();
+        chars_before + 1
+    }
+
+    /// The position at the 1-indexed `line` and `col`, as reported by
+    /// `line_of` and `col_of`. This is the reverse of those, for translating
+    /// positions from tools like editors.
+    ///
+    /// Returns `None` if there is no such line or the line has fewer columns.
+    /// The newline ending a line counts as its last column.
+    pub fn pos_from_line_col(&self, line: usize, col: usize) -> Option<SourcePos> {
+        let index = line.checked_sub(self.line_offset + 1)?;
+        let line_end = *self.newlines.get(index)?;
+        let start = self.first_byte_of_line(line)?;
+
+        let byte = self.text[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(iter::once(self.text.len()))
+            .nth(col.checked_sub(1)?)?;
+
+        (byte <= line_end).then(|| self.pos_from_byte(byte))
     }
 
     /// Get the span in this source that starts at the inclusive byte index
@@ -159,23 +369,59 @@ impl Source {
         Span::new(start_pos, end_pos)
     }
 
+    /// Get the span in this source covering the byte `range`, for interop with
+    /// tools that work with plain byte ranges.
+    ///
+    /// Returns `None` if the range is reversed, extends past the end of the
+    /// source, or doesn't start and end on char boundaries.
+    pub fn span_from_range(&self, range: Range<usize>) -> Option<Span> {
+        let valid = range.start <= range.end
+            && self.text.is_char_boundary(range.start)
+            && self.text.is_char_boundary(range.end);
+
+        valid.then(|| self.span(range.start, range.end))
+    }
+
+    /// Get the empty span at the byte index `byte`, for pointing between
+    /// characters, like where something is missing.
+    pub fn point_span(&self, byte: usize) -> Span {
+        self.span(byte, byte)
+    }
+
     /// Get the span in this source that starts at the inclusive byte index
     /// start and has the given length.
     pub fn span_with_len(&self, start: usize, len: usize) -> Span {
         self.span(start, start + len)
     }
 
-    /// Gives the span of the text on the given line, not including the final newline.
+    /// Gives the span of the text on the given line, not including the final
+    /// newline or the '\r' of a final "\r\n".
+    ///
+    /// Panics if the line is before the line offset, since no position in
+    /// this source is on it.
     pub(crate) fn span_of_line(&self, line: usize) -> Span {
-        let start = self.first_byte_of_line(line);
-        let end = self.first_byte_of_line(line + 1) - 1; // Remove the newline.
+        let Some(start) = self.first_byte_of_line(line) else {
+            panic!("Line {line} is before the first line of `{}`", self.filename);
+        };
+
+        // The line ends at its newline, or at the end of the text if it is the
+        // last line. Lines past the end of the text are empty.
+        let index = line - (self.line_offset + 1);
+        let mut end = self
+            .newlines
+            .get(index)
+            .map_or(start, |&i| i.min(self.text.len()));
+
+        if end > start && self.text[..end].ends_with('\r') {
+            end -= 1;
+        }
         self.span(start, end)
     }
 }
 
 /// An identifier for a `Source`. Use this as a handle to retrieve the `Source`
 /// from the `SourceMap`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SourceId(usize);
 
 /// A range of characters within a `Source`.
@@ -191,14 +437,38 @@ pub struct Span {
 impl Span {
     /// Create a new `Span` from the inclusive start and exclusive end position.
     pub fn new(start: SourcePos, end: SourcePos) -> Self {
-        assert!(start.src_id() == end.src_id());
-        assert!(start.byte() <= end.byte());
+        consistency_assert!(start.src_id() == end.src_id());
+        consistency_assert!(start.byte() <= end.byte());
 
         Self { start, end }
     }
 
-    pub fn union(start: Self, end: Self) -> Self {
-        Self::new(start.start(), end.end())
+    /// The smallest span covering both `a` and `b`, including anything
+    /// between them. Both spans must be in the same `Source`, which is
+    /// checked even in release builds since the result would be meaningless.
+    pub fn union(a: Self, b: Self) -> Self {
+        assert!(
+            a.src_id() == b.src_id(),
+            "Can't take the union of spans in different sources"
+        );
+
+        let start = cmp::min_by_key(a.start, b.start, SourcePos::byte);
+        let end = cmp::max_by_key(a.end, b.end, SourcePos::byte);
+        Self::new(start, end)
+    }
+
+    /// Whether `pos` is within this span. Positions in other sources never
+    /// are, and neither is the exclusive end.
+    pub fn contains(&self, pos: SourcePos) -> bool {
+        pos.src_id() == self.src_id() && self.byte_range().contains(&pos.byte())
+    }
+
+    /// Whether this span and `other` share any characters. Spans that only
+    /// touch, or are in different sources, don't intersect.
+    pub fn intersects(&self, other: Span) -> bool {
+        other.src_id() == self.src_id()
+            && self.start().byte() < other.end().byte()
+            && other.start().byte() < self.end().byte()
     }
 
     /// The id of the `Source` this `Span` is within.
@@ -220,8 +490,12 @@ impl Span {
     pub fn byte_range(&self) -> Range<usize> {
         self.start().byte()..self.end().byte()
     }
-    
 
+    /// The span as a plain byte range. This is the inverse of
+    /// `Source::span_from_range`.
+    pub fn as_range(&self) -> Range<usize> {
+        self.byte_range()
+    }
 }
 
 /// A position of a single character within a `Source`.
@@ -250,4 +524,297 @@ impl SourcePos {
     pub fn byte(&self) -> usize {
         self.byte
     }
+
+    /// The position `n` bytes after this one in `source`.
+    ///
+    /// Returns `None` if that would be past the end of the source or not on a
+    /// char boundary.
+    pub fn checked_add(&self, n: usize, source: &Source) -> Option<SourcePos> {
+        consistency_assert!(self.src_id() == source.id());
+
+        let byte = self.byte.checked_add(n)?;
+        source
+            .text()
+            .is_char_boundary(byte)
+            .then(|| source.pos_from_byte(byte))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_offset() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source_with_line_offset(
+            String::from("test.fern"),
+            String::from("ab\ncd\n"),
+            10,
+        );
+        let source = source_map.get_source(id);
+
+        let c = source.pos_from_line_col(12, 1).unwrap();
+        assert_eq!(c.byte(), 3);
+        assert_eq!((source.line_of(c), source.col_of(c)), (12, 1));
+        assert_eq!(source.pos_from_line_col(11, 2).unwrap().byte(), 1);
+
+        // Lines before the offset aren't in the source, rather than being
+        // mapped onto its first line.
+        assert_eq!(source.pos_from_line_col(10, 1), None);
+        assert_eq!(source.pos_from_line_col(1, 1), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn span_of_line_before_offset() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source_with_line_offset(
+            String::from("test.fern"),
+            String::from("ab\ncd\n"),
+            10,
+        );
+
+        source_map.get_source(id).span_of_line(10);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn sources_from_dir() {
+        use std::{fs, os::unix::fs::symlink};
+
+        let dir = std::env::temp_dir().join(format!("fernc-dir-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.fern"), "fn b() {}").unwrap();
+        fs::write(dir.join("sub/a.fern"), "fn a() {}").unwrap();
+        fs::write(dir.join("a.fern"), [b'f', b'n', 0xff]).unwrap();
+        fs::write(dir.join("notes.txt"), "not fern").unwrap();
+        symlink("..", dir.join("sub/parent")).unwrap();
+
+        let mut source_map = SourceMap::new();
+        let results = source_map
+            .add_sources_from_dir(dir.to_str().unwrap(), ".fern")
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The files come in order of their paths, and the one that isn't
+        // UTF-8 is an error of its own rather than failing the whole load.
+        assert_eq!(results.len(), 3);
+        let err = results[0].as_ref().unwrap_err();
+        assert!(err.to_string().contains("a.fern"), "{err}");
+
+        let texts: Vec<&str> = results[1..]
+            .iter()
+            .map(|id| source_map.get_source(*id.as_ref().unwrap()).text())
+            .collect();
+        assert_eq!(texts, ["fn b() {}", "fn a() {}"]);
+    }
+
+    #[test]
+    fn span_range_round_trip() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("let é = 1;"));
+        let source = source_map.get_source(id);
+
+        let span = source.span(4, 6);
+        assert_eq!(span.as_range(), 4..6);
+        assert_eq!(source.span_from_range(span.as_range()), Some(span));
+        assert_eq!(source.span_from_range(0..11), Some(source.span(0, 11)));
+
+        // Ranges that split a character, run backwards or go past the end
+        // don't make a span.
+        assert_eq!(source.span_from_range(4..5), None);
+        assert_eq!(source.span_from_range(Range { start: 6, end: 4 }), None);
+        assert_eq!(source.span_from_range(0..12), None);
+    }
+
+    #[test]
+    fn bare_cr_newlines() {
+        let text = String::from("a\rb\r\nc\nd");
+
+        let mut source_map = SourceMap::new().with_bare_cr_newlines(true);
+        let id = source_map.add_source(String::from("test.fern"), text.clone());
+        let source = source_map.get_source(id);
+        let lines: Vec<usize> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|c| source.line_of(source.pos_at(text.find(c).unwrap()).unwrap()))
+            .collect();
+        assert_eq!(lines, [1, 2, 3, 4]);
+        assert_eq!(source.text_of_span(source.span_of_line(2)), "b");
+
+        // By default a lone '\r' is part of the line it's on.
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), text);
+        let source = source_map.get_source(id);
+        assert_eq!(source.line_of(source.pos_at(2).unwrap()), 1);
+    }
+
+    #[test]
+    fn checked_add() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("aé;"));
+        let source = source_map.get_source(id);
+        let start = source.pos_at(0).unwrap();
+
+        assert_eq!(start.checked_add(1, source), source.pos_at(1));
+        assert_eq!(start.checked_add(3, source), source.pos_at(3));
+        // The end of the text is a position, but nothing after it is.
+        assert_eq!(start.checked_add(4, source), source.pos_at(4));
+        assert_eq!(start.checked_add(5, source), None);
+        assert_eq!(start.checked_add(usize::MAX, source), None);
+        // Byte 2 is in the middle of the 'é'.
+        assert_eq!(start.checked_add(2, source), None);
+    }
+
+    #[test]
+    fn span_union() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("0123456789"));
+        let source = source_map.get_source(id);
+        let union = |a: (usize, usize), b: (usize, usize)| {
+            Span::union(source.span(a.0, a.1), source.span(b.0, b.1)).byte_range()
+        };
+
+        assert_eq!(union((1, 5), (3, 8)), 1..8);
+        assert_eq!(union((6, 8), (1, 3)), 1..8);
+        assert_eq!(union((1, 8), (3, 5)), 1..8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn span_union_of_different_sources() {
+        let mut source_map = SourceMap::new();
+        let a = source_map.add_source(String::from("a.fern"), String::from("abc"));
+        let b = source_map.add_source(String::from("b.fern"), String::from("abc"));
+
+        Span::union(
+            source_map.get_source(a).span(0, 1),
+            source_map.get_source(b).span(1, 2),
+        );
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let mut source_map = SourceMap::new();
+        let path = std::env::temp_dir().join("fernc-does-not-exist.fern");
+
+        let err = source_map
+            .add_source_from_file(path.to_str().unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(source_map.sources().count(), 0);
+    }
+
+    #[test]
+    fn col_counts_chars() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("é = 1\nb = '名'"));
+        let source = source_map.get_source(id);
+        let col = |byte| source.col_of(source.pos_at(byte).unwrap());
+
+        // The `=` comes after two bytes of `é` and a space.
+        assert_eq!(col(3), 3);
+        assert_eq!(col(5), 5);
+        assert_eq!(col(15), 7);
+        assert_eq!(col(16), 8);
+    }
+
+    #[test]
+    fn line_col_round_trip() {
+        let mut source_map = SourceMap::new();
+        let text = String::from("fn f() {\n    é + 1\n}");
+        let id = source_map.add_source(String::from("test.fern"), text.clone());
+        let source = source_map.get_source(id);
+
+        for pos in (0..=text.len()).filter_map(|byte| source.pos_at(byte)) {
+            let (line, col) = (source.line_of(pos), source.col_of(pos));
+            let found = source.pos_from_line_col(line, col);
+            assert_eq!(found, Some(pos), "at {line}:{col}");
+        }
+
+        // There is nothing past the end of a line or of the text, and lines
+        // and columns start at 1.
+        assert_eq!(source.pos_from_line_col(1, 10), None);
+        assert_eq!(source.pos_from_line_col(4, 1), None);
+        assert_eq!(source.pos_from_line_col(0, 1), None);
+        assert_eq!(source.pos_from_line_col(1, 0), None);
+    }
+
+    #[test]
+    fn span_contains_and_intersects() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("0123456789"));
+        let source = source_map.get_source(id);
+        let span = source.span(2, 5);
+        let pos = |byte| source.pos_at(byte).unwrap();
+
+        // The end is exclusive.
+        assert!(!span.contains(pos(1)));
+        assert!(span.contains(pos(2)));
+        assert!(span.contains(pos(4)));
+        assert!(!span.contains(pos(5)));
+
+        // Nested and overlapping spans intersect, but adjacent and disjoint
+        // ones don't.
+        assert!(span.intersects(source.span(3, 4)));
+        assert!(span.intersects(source.span(0, 8)));
+        assert!(span.intersects(source.span(4, 6)));
+        assert!(!span.intersects(source.span(5, 7)));
+        assert!(!span.intersects(source.span(0, 2)));
+        assert!(!span.intersects(source.span(7, 9)));
+    }
+
+    #[test]
+    fn stdin_source() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(STDIN_NAME.to_owned(), String::from("fn f() {}\nx"));
+        let source = source_map.get_source(id);
+        let x = source.pos_at(10).unwrap();
+
+        assert_eq!(source.filename(), "<stdin>");
+        assert_eq!(source.path(), None);
+        assert_eq!((source.line_of(x), source.col_of(x)), (2, 1));
+    }
+
+    #[test]
+    fn replace_source() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("a b"));
+        source_map.replace_source(id, String::from("a\n  b"));
+
+        let source = source_map.get_source(id);
+        let b = source.pos_at(4).unwrap();
+        assert_eq!(source.id(), id);
+        assert_eq!(source.filename(), "test.fern");
+        assert_eq!((source.line_of(b), source.col_of(b)), (2, 3));
+    }
+
+    #[test]
+    fn get_or_add_from_file_dedups() {
+        let path = std::env::temp_dir().join("fernc-get-or-add.fern");
+        std::fs::write(&path, "fn f() {}").unwrap();
+        let filename = path.to_str().unwrap();
+
+        let mut source_map = SourceMap::new();
+        let first = source_map.get_or_add_from_file(filename).unwrap();
+        let second = source_map.get_or_add_from_file(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(source_map.sources().count(), 1);
+    }
+
+    #[test]
+    fn pos_at() {
+        let mut source_map = SourceMap::new();
+        let id = source_map.add_source(String::from("test.fern"), String::from("aé\nb"));
+        let source = source_map.get_source(id);
+
+        let b = source.pos_at(4).unwrap();
+        assert_eq!((b.byte(), source.line_of(b), source.col_of(b)), (4, 2, 1));
+        assert_eq!(source.pos_at(5).map(|pos| pos.byte()), Some(5));
+
+        assert_eq!(source.pos_at(6), None);
+        assert_eq!(source.pos_at(2), None);
+    }
 }
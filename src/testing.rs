@@ -0,0 +1,66 @@
+//! Helpers for writing tests against the compiler's output.
+
+/// Remove the ANSI escape sequences from `text`, leaving what a user would
+/// read in their terminal. This handles the SGR sequences like `\x1b[1m` our
+/// diagnostics are colored with and the OSC 8 sequences their hyperlinks are
+/// made of.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.clone().next() {
+            // A control sequence is `ESC [`, then parameter and intermediate
+            // bytes, then a single final byte in the range '@'..='~'.
+            Some('[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // An operating system command is `ESC ]` followed by its text up
+            // to a string terminator, which is either `ESC \` or BEL.
+            Some(']') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.clone().next() == Some('\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(strip_ansi("\x1b[1;31merror\x1b[0m: oops"), "error: oops");
+    }
+
+    #[test]
+    fn strips_hyperlinks() {
+        let link = "\x1b]8;;file:///a.fern\x1b\\a.fern\x1b]8;;\x1b\\:1:2";
+        assert_eq!(strip_ansi(link), "a.fern:1:2");
+
+        let bel_link = "\x1b]8;;file:///a.fern\x07a.fern\x1b]8;;\x07";
+        assert_eq!(strip_ansi(bel_link), "a.fern");
+    }
+}
@@ -0,0 +1,26 @@
+//! Measuring how different two strings are, for suggesting corrections to
+//! misspelled names.
+
+/// The Levenshtein distance between `a` and `b`, that is the fewest single
+/// character insertions, deletions, or substitutions that turn one into the
+/// other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    // `row[j]` is the distance between the prefix of `a` seen so far and the
+    // first `j` characters of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
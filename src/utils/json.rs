@@ -0,0 +1,22 @@
+//! Helpers for writing JSON by hand.
+
+use std::fmt::Write;
+
+/// Write `text` as a quoted JSON string.
+pub fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
@@ -1 +1,15 @@
+pub mod edit_distance;
+pub mod json;
 pub mod tree_writer;
+
+/// Assert an internal invariant of the compiler. Unlike `assert!` this is only
+/// checked in debug builds or with the `consistency-checks` feature, so
+/// release builds degrade gracefully instead of aborting.
+macro_rules! consistency_assert {
+    ($($arg:tt)*) => {
+        if cfg!(any(debug_assertions, feature = "consistency-checks")) {
+            assert!($($arg)*);
+        }
+    };
+}
+pub(crate) use consistency_assert;
@@ -1,13 +1,22 @@
 use std::fmt::{Display, Write};
 
+/// What each level of a tree is indented by, unless another indent is given.
+const DEFAULT_INDENT: &str = "  ";
+
 pub struct TreePrinter {
     wr: IndentedWriter<String>,
 }
 
 impl TreePrinter {
     pub fn start(name: &str) -> Self {
+        Self::start_with_indent(name, DEFAULT_INDENT)
+    }
+
+    /// Like `start`, but each level of the tree is indented by `indent`
+    /// instead of two spaces.
+    pub fn start_with_indent(name: &str, indent: &str) -> Self {
         let mut me = Self {
-            wr: IndentedWriter::new(String::new()),
+            wr: IndentedWriter::with_indent(String::new(), indent),
         };
 
         writeln!(me.wr, "{name} {{");
@@ -15,11 +24,22 @@ impl TreePrinter {
         me
     }
 
+    /// Add a field with the value `t`. Control characters in the value are
+    /// escaped, so it always stays on one line.
     pub fn field<T: Display>(mut self, name: &str, t: T) -> Self {
-        writeln!(self.wr, "{name}: {t}");
+        writeln!(self.wr, "{name}: {}", escape_control(&t.to_string()));
+        self
+    }
+
+    /// Add a field whose value is another printed tree, which is written
+    /// as-is.
+    fn child_field(mut self, name: &str, tree: &str) -> Self {
+        writeln!(self.wr, "{name}: {tree}");
         self
     }
 
+    /// Add a field with a list of values. These are written as-is, since
+    /// they are usually printed trees themselves.
     pub fn field_list<T, I: IntoIterator<Item = T>, D: Display, F: FnMut(T) -> D>(
         mut self,
         name: &str,
@@ -51,18 +71,185 @@ impl TreePrinter {
     }
 }
 
+/// How a single node of a tree should be printed. Children are referred to by
+/// some handle `N` rather than printed, so the tree can be walked without
+/// recursion.
+pub enum TreeShape<N> {
+    /// The node is printed as a single piece of text.
+    Leaf(String),
+
+    /// The node is printed with its name followed by its fields in braces.
+    Node {
+        /// The name of the node.
+        name: &'static str,
+
+        /// The fields of the node, in the order they are printed.
+        fields: Vec<(&'static str, TreeField<N>)>,
+    },
+}
+
+/// The value of a field in a `TreeShape::Node`.
+pub enum TreeField<N> {
+    /// The field is just some text.
+    Text(String),
+
+    /// The field is another node.
+    Child(N),
+
+    /// The field is a list of nodes.
+    List(Vec<N>),
+}
+
+/// Print the tree rooted at `root` using `TreePrinter`, where `shape` gives
+/// the shape of each node.
+pub fn print_tree<N>(root: N, shape: &mut impl FnMut(N) -> TreeShape<N>) -> String {
+    let (name, fields) = match shape(root) {
+        TreeShape::Leaf(text) => return escape_control(&text),
+        TreeShape::Node { name, fields } => (name, fields),
+    };
+
+    let mut printer = TreePrinter::start(name);
+    for (field_name, field) in fields {
+        printer = match field {
+            TreeField::Text(text) => printer.field(field_name, text),
+            TreeField::Child(child) => printer.child_field(field_name, &print_tree(child, shape)),
+            TreeField::List(children) => {
+                printer.field_list(field_name, children, |c| print_tree(c, shape))
+            }
+        };
+    }
+
+    printer.finish()
+}
+
+/// Print the tree rooted at `root` exactly like `print_tree` does but with an
+/// explicit stack instead of recursion, so arbitrarily deep trees can't
+/// overflow the call stack.
+pub fn print_tree_iterative<N>(root: N, shape: &mut impl FnMut(N) -> TreeShape<N>) -> String {
+    let mut wr = IndentedWriter::new(String::new());
+    let mut stack = vec![PrintStep::Node(root)];
+
+    while let Some(step) = stack.pop() {
+        match step {
+            PrintStep::Write(text) => {
+                wr.write_str(&text);
+            }
+            PrintStep::Indent => {
+                wr.indent();
+            }
+            PrintStep::Outdent => {
+                wr.outdent();
+            }
+            PrintStep::Node(node) => {
+                let steps = PrintStep::for_shape(shape(node));
+                stack.extend(steps.into_iter().rev());
+            }
+        }
+    }
+
+    wr.to_inner()
+}
+
+/// A piece of work left to do in `print_tree_iterative`.
+enum PrintStep<N> {
+    /// Write the text as it is.
+    Write(String),
+
+    /// Indent the following lines one more level.
+    Indent,
+
+    /// Indent the following lines one less level.
+    Outdent,
+
+    /// Print the node, which adds the steps for its shape.
+    Node(N),
+}
+
+impl<N> PrintStep<N> {
+    /// The steps to print a node with the given shape. These mirror the writes
+    /// `TreePrinter` makes.
+    fn for_shape(shape: TreeShape<N>) -> Vec<Self> {
+        let (name, fields) = match shape {
+            TreeShape::Leaf(text) => return vec![Self::Write(escape_control(&text))],
+            TreeShape::Node { name, fields } => (name, fields),
+        };
+
+        let mut steps = vec![Self::Write(format!("{name} {{\n")), Self::Indent];
+
+        for (field_name, field) in fields {
+            match field {
+                TreeField::Text(text) => {
+                    let text = escape_control(&text);
+                    steps.push(Self::Write(format!("{field_name}: {text}\n")));
+                }
+                TreeField::Child(child) => {
+                    steps.push(Self::Write(format!("{field_name}: ")));
+                    steps.push(Self::Node(child));
+                    steps.push(Self::Write("\n".to_owned()));
+                }
+                TreeField::List(children) => {
+                    let short = children.is_empty();
+
+                    steps.push(Self::Write(format!("{field_name}: [")));
+                    steps.push(Self::Indent);
+                    for child in children {
+                        steps.push(Self::Write("\n".to_owned()));
+                        steps.push(Self::Node(child));
+                    }
+                    steps.push(Self::Outdent);
+                    if !short {
+                        steps.push(Self::Write("\n".to_owned()));
+                    }
+                    steps.push(Self::Write("]\n".to_owned()));
+                }
+            }
+        }
+
+        steps.push(Self::Outdent);
+        steps.push(Self::Write("}".to_owned()));
+        steps
+    }
+}
+
+/// Replace the control characters in `text` with escapes like `\n`, so it
+/// can't break up the lines of a tree.
+fn escape_control(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.extend(c.escape_unicode()),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
 struct IndentedWriter<W: Write> {
     buf: W,
     on_newline: bool,
     depth: usize,
+
+    /// What is written once per level of depth at the start of each line.
+    indent: String,
 }
 
 impl<W: Write> IndentedWriter<W> {
     fn new(buf: W) -> Self {
+        Self::with_indent(buf, DEFAULT_INDENT)
+    }
+
+    /// A writer into `buf` that indents each level by `indent`.
+    fn with_indent(buf: W, indent: &str) -> Self {
         Self {
             buf,
             on_newline: false,
             depth: 0,
+            indent: indent.to_owned(),
         }
     }
 
@@ -86,7 +273,7 @@ impl<W: Write> Write for IndentedWriter<W> {
         for s in s.split_inclusive('\n') {
             if self.on_newline {
                 for _ in 0..self.depth {
-                    self.buf.write_str("  ")?;
+                    self.buf.write_str(&self.indent)?;
                 }
             }
 
@@ -97,3 +284,102 @@ impl<W: Write> Write for IndentedWriter<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// The shape of node `n` of a chain of `Link`s ending in a leaf at 0.
+    fn chain(n: usize) -> TreeShape<usize> {
+        match n {
+            0 => TreeShape::Leaf("end".to_owned()),
+            n => TreeShape::Node {
+                name: "Link",
+                fields: vec![
+                    ("n", TreeField::Text(n.to_string())),
+                    ("next", TreeField::Child(n - 1)),
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn iterative_matches_recursive() {
+        let mut shape = |n: usize| match n {
+            0 => TreeShape::Leaf("leaf".to_owned()),
+            n => TreeShape::Node {
+                name: "Node",
+                fields: vec![
+                    ("text", TreeField::Text(format!("n = {n}"))),
+                    ("child", TreeField::Child(n - 1)),
+                    ("list", TreeField::List((0..n / 2).collect())),
+                    ("empty", TreeField::List(Vec::new())),
+                ],
+            },
+        };
+
+        assert_eq!(
+            print_tree_iterative(6, &mut shape),
+            print_tree(6, &mut shape)
+        );
+    }
+
+    #[test]
+    fn iterative_handles_deep_trees() {
+        const DEPTH: usize = 2000;
+
+        // The stack is far too small to recurse once per level of the tree.
+        let printed = thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| print_tree_iterative(DEPTH, &mut chain))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines.len(), 3 * DEPTH + 1);
+        assert_eq!(lines[2 * DEPTH], format!("{}next: end", "  ".repeat(DEPTH)));
+        assert_eq!(lines.last(), Some(&"}"));
+    }
+
+    #[test]
+    fn newlines_in_fields_are_escaped() {
+        let mut shape = |n: usize| match n {
+            0 => TreeShape::Leaf("a\tb".to_owned()),
+            _ => TreeShape::Node {
+                name: "Str",
+                fields: vec![
+                    ("value", TreeField::Text("one\ntwo".to_owned())),
+                    ("child", TreeField::Child(0)),
+                ],
+            },
+        };
+        let expected = "Str {\n  value: one\\ntwo\n  child: a\\tb\n}";
+
+        assert_eq!(print_tree(1, &mut shape), expected);
+        assert_eq!(print_tree_iterative(1, &mut shape), expected);
+    }
+
+    #[test]
+    fn custom_indent() {
+        let inner = TreePrinter::start_with_indent("Inner", "    ")
+            .field("x", 1)
+            .finish();
+        let outer = TreePrinter::start_with_indent("Outer", "    ")
+            .field("name", "a")
+            .field_list("items", [inner], |t| t)
+            .finish();
+
+        let expected = "\
+Outer {
+    name: a
+    items: [
+        Inner {
+            x: 1
+        }
+    ]
+}";
+        assert_eq!(outer, expected);
+    }
+}